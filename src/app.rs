@@ -1,6 +1,11 @@
+use crate::color::{OutputTransform, ToneMapping};
+use crate::environment::Environment;
 use crate::image::{Image, ImageFormat};
-use crate::renderer::{Parameters, Renderer};
+use crate::light::{Light, LightKind};
+use crate::renderer::{Parameters, RenderMode, Renderer};
 use crate::scene::Scene;
+use crate::sky::Sky;
+use crate::vector::Vec3f;
 use pixels::{Pixels, SurfaceTexture};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
@@ -16,8 +21,11 @@ pub struct App {
     image: Image,
     render_thread: Option<std::thread::JoinHandle<()>>,
     frame_buffer: Arc<Mutex<Option<Vec<u8>>>>,
+    /// Linear radiance of a finished render that has not been developed yet
+    radiance_buffer: Arc<Mutex<Option<Vec<crate::vector::Vec3f>>>>,
     is_rendering: Arc<Mutex<bool>>,
     obj_path: String,
+    environment_path: Option<String>,
     camera_yaw: f32,
     camera_pitch: f32,
     camera_roll: f32,
@@ -26,6 +34,7 @@ pub struct App {
     default_camera_distance: f32,
     default_camera_target: crate::vector::Vec3f,
     camera_dirty: bool,
+    display_dirty: bool,
     egui_state: Option<egui_winit::State>,
     egui_ctx: egui::Context,
     egui_renderer: Option<egui_wgpu::Renderer>,
@@ -50,16 +59,20 @@ impl App {
         height: usize,
         samples: usize,
         max_bounces: usize,
+        russian_roulette_depth: usize,
         debug_mode: bool,
         obj_path: String,
+        environment_path: Option<String>,
     ) -> Self {
         let renderer = Renderer::new(Parameters {
             samples,
             max_ray_depth: max_bounces,
+            russian_roulette_depth,
             debug_mode,
             camera_pos: crate::vector::Vec3f::new(72.0, 72.0, 180.0),
             camera_target: crate::vector::Vec3f::new(72.0, 72.0, 0.0),
             camera_up: crate::vector::Vec3f::new(0.0, 1.0, 0.0),
+            ..Parameters::default()
         });
         let image = Image::new(ImageFormat::PPM, width, height);
 
@@ -73,8 +86,10 @@ impl App {
             image,
             render_thread: None,
             frame_buffer: Arc::new(Mutex::new(Some(initial_buffer))),
+            radiance_buffer: Arc::new(Mutex::new(None)),
             is_rendering: Arc::new(Mutex::new(false)),
             obj_path,
+            environment_path,
             camera_yaw: 0.0,
             camera_pitch: 0.0,
             camera_roll: 0.0,
@@ -83,6 +98,7 @@ impl App {
             default_camera_distance: 180.0,
             default_camera_target: crate::vector::Vec3f::new(72.0, 72.0, 0.0),
             camera_dirty: true,
+            display_dirty: false,
             egui_state: None,
             egui_ctx,
             egui_renderer: None,
@@ -133,6 +149,9 @@ impl App {
 
         println!("Loading scene...");
         self.scene = Scene::load(&self.obj_path);
+        if let (Some(scene), Some(environment_path)) = (&mut self.scene, &self.environment_path) {
+            scene.environment = Environment::load(environment_path);
+        }
         if self.scene.is_some() {
             if let Some(scene) = &self.scene {
                 if let Some(root) = scene.bvh.nodes.get(0) {
@@ -146,6 +165,7 @@ impl App {
                     let dist = (diag * 1.4).clamp(10.0, 1000.0);
                     self.camera_distance = dist;
                     self.default_camera_distance = dist;
+                    self.renderer.parameters.ambient_occlusion_radius = diag * 0.1;
 
                     self.camera_yaw = 0.0;
                     self.camera_pitch = 0.0;
@@ -359,7 +379,29 @@ impl App {
                         let mut orbit_sensitivity = self.orbit_sensitivity;
                         let mut zoom_sensitivity = self.zoom_sensitivity;
                         let mut render_while_dragging = self.render_while_dragging;
+                        let mut environment_rotation = self.renderer.parameters.environment_rotation;
+                        let mut environment_intensity = self.renderer.parameters.environment_intensity;
+                        let mut physical_sky = self.renderer.parameters.sky.is_some();
+                        let sky = self.renderer.parameters.sky.unwrap_or_default();
+                        let mut sun_elevation = sky.sun_elevation;
+                        let mut sun_azimuth = sky.sun_azimuth;
+                        let mut turbidity = sky.turbidity;
+                        let mut exposure = self.renderer.parameters.exposure;
+                        let mut white_balance = self.renderer.parameters.white_balance;
+                        let mut tone_mapping = self.renderer.parameters.tone_mapping;
+                        let mut output_transform = self.renderer.parameters.output_transform;
+                        let mut render_mode = self.renderer.parameters.render_mode;
+                        let mut ambient_occlusion_radius = self.renderer.parameters.ambient_occlusion_radius;
+                        let max_occlusion_radius = self.default_camera_distance;
+                        let has_scene = self.scene.is_some();
+                        let mut lights = self.scene.as_ref().map(|scene| scene.lights.clone()).unwrap_or_default();
+                        let light_center = self.default_camera_target;
+                        let light_size = self.default_camera_distance * 0.1;
                         let mut camera_changed = false;
+                        let mut environment_changed = false;
+                        let mut display_changed = false;
+                        let mut render_mode_changed = false;
+                        let mut lights_changed = false;
                         let mut settings_changed = false;
                         
                         let output = self.egui_ctx.run(raw_input, |ctx| {
@@ -428,6 +470,215 @@ impl App {
                                         ui.label("Ready");
                                     }
                                 });
+
+                            egui::Window::new("Environment")
+                                .default_pos(egui::pos2(10.0, 330.0))
+                                .resizable(false)
+                                .show(ctx, |ui| {
+                                    let mut rotation = environment_rotation.to_degrees();
+                                    if ui.add(egui::Slider::new(&mut rotation, -180.0..=180.0).text("Rotation"))
+                                        .changed() {
+                                        environment_rotation = rotation.to_radians();
+                                        environment_changed = true;
+                                    }
+                                    if ui
+                                        .add(egui::Slider::new(&mut environment_intensity, 0.0..=10.0).text("Intensity"))
+                                        .changed()
+                                    {
+                                        environment_changed = true;
+                                    }
+
+                                    ui.separator();
+                                    if ui.checkbox(&mut physical_sky, "Physical sky").changed() {
+                                        environment_changed = true;
+                                    }
+                                    if physical_sky {
+                                        let mut elevation = sun_elevation.to_degrees();
+                                        let mut azimuth = sun_azimuth.to_degrees();
+                                        if ui.add(egui::Slider::new(&mut elevation, -5.0..=90.0).text("Sun elevation"))
+                                            .changed() {
+                                            sun_elevation = elevation.to_radians();
+                                            environment_changed = true;
+                                        }
+                                        if ui.add(egui::Slider::new(&mut azimuth, -180.0..=180.0).text("Sun azimuth"))
+                                            .changed() {
+                                            sun_azimuth = azimuth.to_radians();
+                                            environment_changed = true;
+                                        }
+                                        if ui.add(egui::Slider::new(&mut turbidity, 2.0..=10.0).text("Turbidity"))
+                                            .changed() {
+                                            environment_changed = true;
+                                        }
+                                    }
+                                });
+
+                            egui::Window::new("Display")
+                                .default_pos(egui::pos2(10.0, 560.0))
+                                .resizable(false)
+                                .show(ctx, |ui| {
+                                    if ui.add(egui::Slider::new(&mut exposure, -10.0..=10.0).text("Exposure (EV)"))
+                                        .changed() {
+                                        display_changed = true;
+                                    }
+                                    if ui
+                                        .add(egui::Slider::new(&mut white_balance, 2500.0..=12000.0).text("White balance (K)"))
+                                        .changed()
+                                    {
+                                        display_changed = true;
+                                    }
+                                    egui::ComboBox::from_label("Tone mapping")
+                                        .selected_text(tone_mapping.name())
+                                        .show_ui(ui, |ui| {
+                                            for operator in ToneMapping::ALL {
+                                                if ui
+                                                    .selectable_value(&mut tone_mapping, operator, operator.name())
+                                                    .changed()
+                                                {
+                                                    display_changed = true;
+                                                }
+                                            }
+                                        });
+                                    egui::ComboBox::from_label("Output")
+                                        .selected_text(output_transform.name())
+                                        .show_ui(ui, |ui| {
+                                            for transform in OutputTransform::ALL {
+                                                if ui
+                                                    .selectable_value(&mut output_transform, transform, transform.name())
+                                                    .changed()
+                                                {
+                                                    display_changed = true;
+                                                }
+                                            }
+                                        });
+
+                                    ui.separator();
+                                    egui::ComboBox::from_label("Render mode")
+                                        .selected_text(render_mode.name())
+                                        .show_ui(ui, |ui| {
+                                            for mode in RenderMode::ALL {
+                                                if ui.selectable_value(&mut render_mode, mode, mode.name()).changed() {
+                                                    render_mode_changed = true;
+                                                }
+                                            }
+                                        });
+                                    if render_mode == RenderMode::AmbientOcclusion {
+                                        if ui
+                                            .add(
+                                                egui::Slider::new(&mut ambient_occlusion_radius, 0.0..=max_occlusion_radius)
+                                                    .text("Occlusion radius"),
+                                            )
+                                            .changed()
+                                        {
+                                            render_mode_changed = true;
+                                        }
+                                    }
+                                });
+
+                            if has_scene {
+                                egui::Window::new("Lights")
+                                    .default_pos(egui::pos2(260.0, 10.0))
+                                    .resizable(false)
+                                    .show(ctx, |ui| {
+                                        ui.horizontal(|ui| {
+                                            let above = light_center + Vec3f::new(0.0, light_size * 5.0, 0.0);
+                                            let down = Vec3f::new(0.0, -1.0, 0.0);
+                                            let mut new_kind = None;
+                                            if ui.button("Point").clicked() {
+                                                new_kind = Some(LightKind::Point { position: above });
+                                            }
+                                            if ui.button("Spot").clicked() {
+                                                new_kind = Some(LightKind::Spot {
+                                                    position: above,
+                                                    direction: down,
+                                                    inner_angle: 20.0f32.to_radians(),
+                                                    outer_angle: 30.0f32.to_radians(),
+                                                });
+                                            }
+                                            if ui.button("Directional").clicked() {
+                                                new_kind = Some(LightKind::Directional { direction: Vec3f::new(-0.3, -1.0, -0.5) });
+                                            }
+                                            if ui.button("Area").clicked() {
+                                                new_kind = Some(LightKind::Quad {
+                                                    corner: above - Vec3f::new(light_size, 0.0, light_size) * 0.5,
+                                                    edge_u: Vec3f::new(0.0, 0.0, light_size),
+                                                    edge_v: Vec3f::new(light_size, 0.0, 0.0),
+                                                });
+                                            }
+                                            if let Some(kind) = new_kind {
+                                                let intensity = match kind {
+                                                    LightKind::Point { .. } | LightKind::Spot { .. } => {
+                                                        light_size * light_size * 25.0
+                                                    }
+                                                    _ => 1.0,
+                                                };
+                                                lights.push(Light { kind, color: Vec3f::from(1.0), intensity });
+                                                lights_changed = true;
+                                            }
+                                        });
+
+                                        let mut removed = None;
+                                        for (i, light) in lights.iter_mut().enumerate() {
+                                            egui::CollapsingHeader::new(format!("{} {}", light.kind.name(), i + 1))
+                                                .id_source(i)
+                                                .show(ui, |ui| {
+                                                    let drag_speed = light_size * 0.05;
+                                                    match &mut light.kind {
+                                                        LightKind::Point { position } => {
+                                                            lights_changed |= vector_edit(ui, "Position", position, drag_speed);
+                                                        }
+                                                        LightKind::Spot { position, direction, inner_angle, outer_angle } => {
+                                                            lights_changed |= vector_edit(ui, "Position", position, drag_speed);
+                                                            lights_changed |= vector_edit(ui, "Direction", direction, 0.01);
+                                                            let mut inner = inner_angle.to_degrees();
+                                                            let mut outer = outer_angle.to_degrees();
+                                                            if ui.add(egui::Slider::new(&mut inner, 0.0..=90.0).text("Inner angle"))
+                                                                .changed() {
+                                                                *inner_angle = inner.to_radians();
+                                                                lights_changed = true;
+                                                            }
+                                                            if ui.add(egui::Slider::new(&mut outer, 0.0..=90.0).text("Outer angle"))
+                                                                .changed() {
+                                                                *outer_angle = outer.to_radians();
+                                                                lights_changed = true;
+                                                            }
+                                                        }
+                                                        LightKind::Directional { direction } => {
+                                                            lights_changed |= vector_edit(ui, "Direction", direction, 0.01);
+                                                        }
+                                                        LightKind::Quad { corner, edge_u, edge_v } => {
+                                                            lights_changed |= vector_edit(ui, "Corner", corner, drag_speed);
+                                                            lights_changed |= vector_edit(ui, "Edge U", edge_u, drag_speed);
+                                                            lights_changed |= vector_edit(ui, "Edge V", edge_v, drag_speed);
+                                                        }
+                                                    }
+                                                    ui.horizontal(|ui| {
+                                                        ui.label("Color");
+                                                        if ui.color_edit_button_rgb(&mut light.color.data).changed() {
+                                                            lights_changed = true;
+                                                        }
+                                                    });
+                                                    if ui
+                                                        .add(
+                                                            egui::DragValue::new(&mut light.intensity)
+                                                                .speed(0.1)
+                                                                .clamp_range(0.0..=f32::MAX)
+                                                                .prefix("Intensity: "),
+                                                        )
+                                                        .changed()
+                                                    {
+                                                        lights_changed = true;
+                                                    }
+                                                    if ui.button("Remove").clicked() {
+                                                        removed = Some(i);
+                                                    }
+                                                });
+                                        }
+                                        if let Some(i) = removed {
+                                            lights.remove(i);
+                                            lights_changed = true;
+                                        }
+                                    });
+                            }
                         });
                         
                         if camera_changed {
@@ -439,6 +690,37 @@ impl App {
                             self.camera_dirty = true;
                             self.last_ui_change = Some(Instant::now());
                         }
+                        if environment_changed {
+                            self.renderer.parameters.environment_rotation = environment_rotation;
+                            self.renderer.parameters.environment_intensity = environment_intensity;
+                            self.renderer.parameters.sky = if physical_sky {
+                                Some(Sky::new(sun_elevation, sun_azimuth, turbidity))
+                            } else {
+                                None
+                            };
+                            self.camera_dirty = true;
+                            self.last_ui_change = Some(Instant::now());
+                        }
+                        if display_changed {
+                            self.renderer.parameters.exposure = exposure;
+                            self.renderer.parameters.white_balance = white_balance;
+                            self.renderer.parameters.tone_mapping = tone_mapping;
+                            self.renderer.parameters.output_transform = output_transform;
+                            self.display_dirty = true;
+                        }
+                        if render_mode_changed {
+                            self.renderer.parameters.render_mode = render_mode;
+                            self.renderer.parameters.ambient_occlusion_radius = ambient_occlusion_radius;
+                            self.camera_dirty = true;
+                            self.last_ui_change = Some(Instant::now());
+                        }
+                        if lights_changed {
+                            if let Some(scene) = &mut self.scene {
+                                scene.lights = lights;
+                            }
+                            self.camera_dirty = true;
+                            self.last_ui_change = Some(Instant::now());
+                        }
                         if settings_changed {
                             self.rotation_speed = rotation_speed;
                             self.auto_rotate = auto_rotate;
@@ -561,7 +843,7 @@ impl App {
         
         let width = self.image.width;
         let height = self.image.height;
-        let frame_buffer = self.frame_buffer.clone();
+        let radiance_buffer = self.radiance_buffer.clone();
         let is_rendering = self.is_rendering.clone();
 
         self.render_thread = Some(std::thread::spawn(move || {
@@ -569,15 +851,8 @@ impl App {
             let mut image = Image::new(ImageFormat::PPM, width, height);
             renderer.render_to_image(&scene, &mut image);
 
-            let mut rgba_buffer = vec![0u8; width * height * 4];
-            for i in 0..width * height {
-                rgba_buffer[i * 4] = image.bytes[i * 3];
-                rgba_buffer[i * 4 + 1] = image.bytes[i * 3 + 1];
-                rgba_buffer[i * 4 + 2] = image.bytes[i * 3 + 2];
-                rgba_buffer[i * 4 + 3] = 255;
-            }
-
-            *frame_buffer.lock().unwrap() = Some(rgba_buffer);
+            // Developed on the main thread so display changes made during the render apply
+            *radiance_buffer.lock().unwrap() = Some(image.radiance);
             *is_rendering.lock().unwrap() = false;
 
             println!("Rendering completed in {} ms", start.elapsed().as_millis());
@@ -585,6 +860,16 @@ impl App {
     }
 
     fn update(&mut self, pixels: &mut Pixels) -> bool {
+        let finished_render = self.radiance_buffer.lock().unwrap().take();
+        if let Some(radiance) = finished_render {
+            self.image.radiance = radiance;
+            self.display_dirty = true;
+        }
+        if self.display_dirty {
+            self.display_dirty = false;
+            self.develop_frame();
+        }
+
         if let Some(buffer) = self.frame_buffer.lock().unwrap().as_ref() {
             let frame = pixels.frame_mut();
             frame.copy_from_slice(&buffer);
@@ -592,5 +877,33 @@ impl App {
         }
         false
     }
+
+    /// Applies the current exposure, white balance and tone mapping to the last finished render
+    fn develop_frame(&mut self) {
+        if self.image.radiance.is_empty() {
+            return;
+        }
+        self.image.bytes = self.renderer.develop(&self.image.radiance);
+
+        let mut rgba_buffer = vec![0u8; self.image.width * self.image.height * 4];
+        for i in 0..self.image.width * self.image.height {
+            rgba_buffer[i * 4] = self.image.bytes[i * 3];
+            rgba_buffer[i * 4 + 1] = self.image.bytes[i * 3 + 1];
+            rgba_buffer[i * 4 + 2] = self.image.bytes[i * 3 + 2];
+            rgba_buffer[i * 4 + 3] = 255;
+        }
+        *self.frame_buffer.lock().unwrap() = Some(rgba_buffer);
+    }
 }
 
+/// Draggable x, y and z fields on one row, returns whether any of them changed
+fn vector_edit(ui: &mut egui::Ui, label: &str, vector: &mut Vec3f, speed: f32) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        for (axis, value) in ["x: ", "y: ", "z: "].iter().zip(vector.data.iter_mut()) {
+            changed |= ui.add(egui::DragValue::new(value).speed(speed).prefix(*axis)).changed();
+        }
+    });
+    return changed;
+}
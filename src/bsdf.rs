@@ -0,0 +1,585 @@
+use crate::scene::Material;
+use crate::vector::{Vec3Swizzles, Vec3f};
+
+/// Roughness is squared into alpha, this keeps fully smooth materials numerically stable
+const MIN_ALPHA: f32 = 1e-3;
+/// Wavelengths in nanometres standing in for the red, green and blue channels
+const FILM_WAVELENGTHS: [f32; 3] = [650.0, 550.0, 450.0];
+
+/// Complex index of refraction of a conductor per color channel
+#[derive(Clone, Copy)]
+pub struct ComplexIor {
+    pub eta: Vec3f,
+    /// Extinction coefficient
+    pub k: Vec3f,
+}
+
+impl Default for ComplexIor {
+    fn default() -> Self {
+        return Self {
+            eta: Vec3f::from(1.0),
+            k: Vec3f::from(0.0),
+        };
+    }
+}
+
+/// Measured metals, sampled at the red, green and blue film wavelengths
+#[derive(Clone, Copy, PartialEq)]
+pub enum Metal {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+    Iron,
+}
+
+impl Metal {
+    pub const ALL: [Self; 5] = [
+        Self::Gold,
+        Self::Copper,
+        Self::Aluminium,
+        Self::Silver,
+        Self::Iron,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gold => return "gold",
+            Self::Copper => return "copper",
+            Self::Aluminium => return "aluminium",
+            Self::Silver => return "silver",
+            Self::Iron => return "iron",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        return Self::ALL
+            .into_iter()
+            .find(|metal| metal.name().eq_ignore_ascii_case(name));
+    }
+
+    pub fn complex_ior(self) -> ComplexIor {
+        let (eta, k) = match self {
+            Self::Gold => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+            Self::Copper => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+            Self::Aluminium => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+            Self::Silver => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+            Self::Iron => ([2.911, 2.950, 2.585], [3.089, 2.932, 2.767]),
+        };
+        return ComplexIor {
+            eta: Vec3f::from(eta),
+            k: Vec3f::from(k),
+        };
+    }
+}
+
+/// Surface response at a hit. The opaque part is a GGX specular lobe over a Lambert diffuse base
+/// with an optional sheen and subsurface entry, blended towards a conductor as `metallic` goes to 1.0.
+/// The conductor is tinted by the base color unless the material gives its complex IOR.
+/// `transmission` fades it into a rough GGX dielectric that reflects and refracts.
+/// An optional clearcoat sits on top and dims whatever it lets through.
+/// A thin film turns the Fresnel terms of the specular and dielectric lobes iridescent.
+pub struct Bsdf {
+    normal: Vec3f,
+    tangent: Vec3f,
+    bitangent: Vec3f,
+    diffuse_color: Vec3f,
+    /// Share of the diffuse lobe that refracts into the surface for a random walk
+    subsurface: f32,
+    subsurface_weight: f32,
+    sheen_color: Vec3f,
+    /// Reflectance at normal incidence
+    specular_color: Vec3f,
+    dielectric_specular: Vec3f,
+    conductor: Option<ComplexIor>,
+    metallic: f32,
+    /// Roughness along the tangent and the bitangent
+    alpha: [f32; 2],
+    film_thickness: f32,
+    film_ior: f32,
+    clearcoat: f32,
+    clearcoat_alpha: [f32; 2],
+    clearcoat_eta: f32,
+    transmission: f32,
+    reflection_tint: Vec3f,
+    transmission_tint: Vec3f,
+    /// Index of refraction behind the surface relative to the side `normal` points to
+    eta: f32,
+}
+
+pub struct BsdfSample {
+    pub direction: Vec3f,
+    /// BSDF value times cosine divided by the pdf, what the path throughput is multiplied by
+    pub weight: Vec3f,
+    /// Solid angle density of picking `direction`
+    pub pdf: f32,
+    /// Whether `direction` starts a subsurface random walk rather than a regular bounce
+    pub subsurface: bool,
+}
+
+impl Bsdf {
+    /// `normal` faces the incoming ray, `front_face` tells whether that is outside the material.
+    /// `tangent` orients anisotropic highlights, any frame is used when it is zero or parallel to `normal`
+    pub fn new(
+        material: &Material,
+        base_color: Vec3f,
+        normal: Vec3f,
+        tangent: Vec3f,
+        front_face: bool,
+    ) -> Self {
+        let tangent = tangent - normal * Vec3f::dot(normal, tangent);
+        let (tangent, bitangent) = if tangent.length() > 1e-6 {
+            let tangent = tangent.normalized();
+            (tangent, Vec3f::cross(normal, tangent))
+        } else {
+            Vec3f::orthonormal_basis(normal)
+        };
+        let angle = material.anisotropy_rotation * std::f32::consts::TAU;
+        let (sin, cos) = f32::sin_cos(angle);
+        let (tangent, bitangent) = (
+            tangent * cos + bitangent * sin,
+            bitangent * cos - tangent * sin,
+        );
+
+        let alpha = material.roughness * material.roughness;
+        // Same mapping as Disney and Blender, full anisotropy gives a 10:1 ratio
+        let aspect = f32::sqrt(1.0 - 0.9 * material.anisotropy.clamp(0.0, 1.0));
+        let clearcoat_alpha = f32::max(
+            material.clearcoat_roughness * material.clearcoat_roughness,
+            MIN_ALPHA,
+        );
+        let dielectric_f0 = f32::powi((material.ior - 1.0) / (material.ior + 1.0), 2);
+        let metallic = material.metallic.clamp(0.0, 1.0);
+        // Walks start from the outside, rays already inside do not scatter into the volume again
+        let subsurface = if front_face {
+            material.subsurface.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        return Self {
+            normal,
+            tangent,
+            bitangent,
+            diffuse_color: base_color * ((1.0 - metallic) * (1.0 - subsurface)),
+            subsurface,
+            subsurface_weight: subsurface * (1.0 - metallic),
+            sheen_color: material.sheen_tint * (material.sheen * (1.0 - metallic)),
+            specular_color: Vec3f::lerp(
+                material.specular_tint * dielectric_f0,
+                match material.conductor {
+                    Some(conductor) => Self::fresnel_conductor(1.0, conductor),
+                    None => base_color,
+                },
+                metallic,
+            ),
+            dielectric_specular: material.specular_tint * dielectric_f0,
+            conductor: material.conductor,
+            metallic,
+            alpha: [
+                f32::max(alpha / aspect, MIN_ALPHA),
+                f32::max(alpha * aspect, MIN_ALPHA),
+            ],
+            // Films and coats are only on the outside of the surface
+            film_thickness: if front_face {
+                material.thin_film_thickness
+            } else {
+                0.0
+            },
+            film_ior: material.thin_film_ior,
+            clearcoat: if front_face {
+                material.clearcoat.clamp(0.0, 1.0)
+            } else {
+                0.0
+            },
+            clearcoat_alpha: [clearcoat_alpha, clearcoat_alpha],
+            clearcoat_eta: material.clearcoat_ior,
+            transmission: material.transmission.clamp(0.0, 1.0),
+            reflection_tint: material.specular_tint,
+            transmission_tint: base_color,
+            eta: if front_face {
+                material.ior
+            } else {
+                1.0 / material.ior
+            },
+        };
+    }
+
+    /// Returns the BSDF value and the solid angle density of `sample` picking `incoming`.
+    /// Both directions point away from the surface.
+    pub fn evaluate(&self, outgoing: Vec3f, incoming: Vec3f) -> (Vec3f, f32) {
+        let wo = self.to_local(outgoing);
+        let wi = self.to_local(incoming);
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return (Vec3f::from(0.0), 0.0);
+        }
+
+        let (opaque, opaque_pdf) = self.evaluate_opaque(wo, wi);
+        let (dielectric, dielectric_pdf) = self.evaluate_dielectric(wo, wi);
+        let base = opaque * (1.0 - self.transmission) + dielectric * self.transmission;
+        let base_pdf = opaque_pdf * (1.0 - self.transmission) + dielectric_pdf * self.transmission;
+        if self.clearcoat <= 0.0 {
+            return (base, base_pdf);
+        }
+
+        let (coat, coat_pdf) = self.evaluate_clearcoat(wo, wi);
+        let coat_probability = self.clearcoat_probability(wo.z());
+        let attenuation =
+            1.0 - self.clearcoat * Self::fresnel_dielectric(wo.z(), self.clearcoat_eta);
+        return (
+            coat + base * attenuation,
+            coat_probability * coat_pdf + (1.0 - coat_probability) * base_pdf,
+        );
+    }
+
+    fn evaluate_clearcoat(&self, wo: Vec3f, wi: Vec3f) -> (Vec3f, f32) {
+        if wi.z() <= 0.0 {
+            return (Vec3f::from(0.0), 0.0);
+        }
+
+        let alpha = self.clearcoat_alpha;
+        let h = (wo + wi).normalized();
+        let fresnel = Self::fresnel_dielectric(Vec3f::dot(wo, h), self.clearcoat_eta);
+        let d = Self::ggx_d(h, alpha);
+        let value =
+            self.clearcoat * fresnel * d * Self::smith_g2(wo, wi, alpha) / (4.0 * wo.z() * wi.z());
+        let pdf = d * Self::smith_g1(wo, alpha) / (4.0 * wo.z());
+        return (Vec3f::from(value), pdf);
+    }
+
+    fn evaluate_opaque(&self, wo: Vec3f, wi: Vec3f) -> (Vec3f, f32) {
+        let specular_probability = self.specular_probability(wo.z());
+        let diffuse_transmission = 1.0 - self.specular_fresnel(wo.z()).luminance();
+        if wi.z() <= 0.0 {
+            if self.subsurface <= 0.0 {
+                return (Vec3f::from(0.0), 0.0);
+            }
+            // Color is picked up during the walk, the entry only accounts for the surface
+            let value = self.subsurface_weight * diffuse_transmission / std::f32::consts::PI;
+            let pdf =
+                (1.0 - specular_probability) * self.subsurface * -wi.z() / std::f32::consts::PI;
+            return (Vec3f::from(value), pdf);
+        }
+
+        let h = (wo + wi).normalized();
+        let fresnel = self.specular_fresnel(Vec3f::dot(wo, h));
+
+        let d = Self::ggx_d(h, self.alpha);
+        let specular = fresnel * (d * Self::smith_g2(wo, wi, self.alpha) / (4.0 * wo.z() * wi.z()));
+        let specular_pdf = d * Self::smith_g1(wo, self.alpha) / (4.0 * wo.z());
+
+        // Disney style sheen, rides along with the diffuse lobe and its sampling
+        let sheen = self.sheen_color * Self::schlick_weight(Vec3f::dot(wi, h));
+        let diffuse = self.diffuse_color * (diffuse_transmission / std::f32::consts::PI) + sheen;
+        let diffuse_pdf = (1.0 - self.subsurface) * wi.z() / std::f32::consts::PI;
+
+        return (
+            specular + diffuse,
+            specular_probability * specular_pdf + (1.0 - specular_probability) * diffuse_pdf,
+        );
+    }
+
+    /// Rough dielectric reflection and refraction, Walter et al. 2007
+    fn evaluate_dielectric(&self, wo: Vec3f, wi: Vec3f) -> (Vec3f, f32) {
+        if self.transmission <= 0.0 {
+            return (Vec3f::from(0.0), 0.0);
+        }
+
+        if wi.z() > 0.0 {
+            let h = (wo + wi).normalized();
+            let fresnel = Self::fresnel_dielectric(Vec3f::dot(wo, h), self.eta);
+            let d = Self::ggx_d(h, self.alpha);
+            let value = self.reflection_tint
+                * self.dielectric_fresnel(Vec3f::dot(wo, h), fresnel)
+                * (d * Self::smith_g2(wo, wi, self.alpha) / (4.0 * wo.z() * wi.z()));
+            let pdf = fresnel * d * Self::smith_g1(wo, self.alpha) / (4.0 * wo.z());
+            return (value, pdf);
+        }
+
+        // The half vector of a refraction lies between the directions weighted by their media
+        let mut h = (wi * self.eta + wo).normalized();
+        if h.z() < 0.0 {
+            h = h.reversed();
+        }
+        let wo_dot_h = Vec3f::dot(wo, h);
+        let wi_dot_h = Vec3f::dot(wi, h);
+        if wo_dot_h <= 0.0 || wi_dot_h >= 0.0 {
+            return (Vec3f::from(0.0), 0.0);
+        }
+
+        let fresnel = Self::fresnel_dielectric(wo_dot_h, self.eta);
+        let d = Self::ggx_d(h, self.alpha);
+        let denominator = f32::powi(wi_dot_h + wo_dot_h / self.eta, 2);
+        // Radiance is compressed into the smaller solid angle of the denser medium
+        let value = self.transmission_tint
+            * (Vec3f::from(1.0) - self.dielectric_fresnel(wo_dot_h, fresnel))
+            * (d * Self::smith_g2(wo, wi, self.alpha) * f32::abs(wi_dot_h * wo_dot_h)
+                / (f32::abs(wi.z()) * wo.z() * denominator * self.eta * self.eta));
+        let pdf =
+            (1.0 - fresnel) * d * Self::smith_g1(wo, self.alpha) * wo_dot_h * f32::abs(wi_dot_h)
+                / (wo.z() * denominator);
+        return (value, pdf);
+    }
+
+    /// Picks an incoming direction for light leaving towards `outgoing`,
+    /// `None` when the sample carries no light, like a reflection that ends up below the surface
+    pub fn sample(&self, outgoing: Vec3f, rng_state: &mut u32) -> Option<BsdfSample> {
+        let wo = self.to_local(outgoing);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let mut subsurface = false;
+        let (direction, transmitted) =
+            if Vec3f::rand_f32(rng_state) < self.clearcoat_probability(wo.z()) {
+                let h = Self::sample_visible_normal(
+                    wo,
+                    self.clearcoat_alpha,
+                    Vec3f::rand_f32(rng_state),
+                    Vec3f::rand_f32(rng_state),
+                );
+                (self.to_world(Vec3f::reflect(wo.reversed(), h)), false)
+            } else if Vec3f::rand_f32(rng_state) < self.transmission {
+                let h = Self::sample_visible_normal(
+                    wo,
+                    self.alpha,
+                    Vec3f::rand_f32(rng_state),
+                    Vec3f::rand_f32(rng_state),
+                );
+                // Total internal reflection shows up as a Fresnel term of 1.0
+                let fresnel = Self::fresnel_dielectric(Vec3f::dot(wo, h), self.eta);
+                let refracted = Vec3f::refract(wo.reversed(), h, 1.0 / self.eta);
+                match refracted {
+                    Some(refracted) if Vec3f::rand_f32(rng_state) >= fresnel => {
+                        (self.to_world(refracted), true)
+                    }
+                    _ => (self.to_world(Vec3f::reflect(wo.reversed(), h)), false),
+                }
+            } else if Vec3f::rand_f32(rng_state) < self.specular_probability(wo.z()) {
+                let h = Self::sample_visible_normal(
+                    wo,
+                    self.alpha,
+                    Vec3f::rand_f32(rng_state),
+                    Vec3f::rand_f32(rng_state),
+                );
+                (self.to_world(Vec3f::reflect(wo.reversed(), h)), false)
+            } else if Vec3f::rand_f32(rng_state) < self.subsurface {
+                subsurface = true;
+                (
+                    Vec3f::rand_cosine_hemisphere(rng_state, self.normal.reversed()),
+                    true,
+                )
+            } else {
+                (Vec3f::rand_cosine_hemisphere(rng_state, self.normal), false)
+            };
+        // Steep microfacets can send a sample to the other side than its lobe covers,
+        // the pdf of the lobes on that side does not account for it
+        if (Vec3f::dot(direction, self.normal) < 0.0) != transmitted {
+            return None;
+        }
+
+        let (value, pdf) = self.evaluate(outgoing, direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+        return Some(BsdfSample {
+            direction,
+            weight: value * (f32::abs(Vec3f::dot(self.normal, direction)) / pdf),
+            pdf,
+            subsurface,
+        });
+    }
+
+    /// Splits samples between the lobes by their expected contribution
+    fn specular_probability(&self, cos_theta: f32) -> f32 {
+        let specular = self.specular_fresnel(cos_theta).luminance();
+        let diffuse = (self.diffuse_color.luminance() + self.subsurface_weight) * (1.0 - specular);
+        if specular + diffuse <= 0.0 {
+            return 0.5;
+        }
+        return (specular / (specular + diffuse)).clamp(0.1, 0.9);
+    }
+
+    /// Share of samples spent on the clearcoat, zero without one
+    fn clearcoat_probability(&self, cos_theta: f32) -> f32 {
+        if self.clearcoat <= 0.0 {
+            return 0.0;
+        }
+        let coat = self.clearcoat * Self::fresnel_dielectric(cos_theta, self.clearcoat_eta);
+        return coat.clamp(0.1, 0.9);
+    }
+
+    /// Reflectance of the opaque specular lobe
+    fn specular_fresnel(&self, cos_theta: f32) -> Vec3f {
+        if self.film_thickness <= 0.0 {
+            let Some(conductor) = self.conductor else {
+                return Self::schlick(self.specular_color, cos_theta);
+            };
+            return Vec3f::lerp(
+                Self::schlick(self.dielectric_specular, cos_theta),
+                Self::fresnel_conductor(cos_theta, conductor),
+                self.metallic,
+            );
+        }
+        // Metals and dielectrics alike become the real index that reflects `specular_color` head on
+        let mut substrate_ior = Vec3f::from(0.0);
+        for i in 0..3 {
+            let r = f32::sqrt(self.specular_color.data[i].clamp(0.0, 0.99));
+            substrate_ior.data[i] = (1.0 + r) / (1.0 - r);
+        }
+        return Self::thin_film(cos_theta, self.film_thickness, self.film_ior, substrate_ior);
+    }
+
+    /// Reflectance of the dielectric lobe, sampling keeps using the plain `fresnel` it is based on
+    fn dielectric_fresnel(&self, cos_theta: f32, fresnel: f32) -> Vec3f {
+        if self.film_thickness <= 0.0 {
+            return Vec3f::from(fresnel);
+        }
+        return Self::thin_film(
+            cos_theta,
+            self.film_thickness,
+            self.film_ior,
+            Vec3f::from(self.eta),
+        );
+    }
+
+    /// Airy reflectance of a film between air and a substrate, from the interference
+    /// of light reflected off its top and bottom, averaged over both polarizations
+    fn thin_film(cos_theta: f32, thickness: f32, film_ior: f32, substrate_ior: Vec3f) -> Vec3f {
+        let cos_1 = cos_theta.clamp(0.0, 1.0);
+        let sin_2_1 = 1.0 - cos_1 * cos_1;
+        let sin_2_2 = sin_2_1 / (film_ior * film_ior);
+        if sin_2_2 >= 1.0 {
+            return Vec3f::from(1.0);
+        }
+        let cos_2 = f32::sqrt(1.0 - sin_2_2);
+
+        let mut reflectance = Vec3f::from(0.0);
+        for i in 0..3 {
+            let ior = substrate_ior.data[i];
+            let cos_3 = f32::sqrt(f32::max(1.0 - sin_2_1 / (ior * ior), 0.0));
+            let phase =
+                4.0 * std::f32::consts::PI * film_ior * thickness * cos_2 / FILM_WAVELENGTHS[i];
+
+            let polarizations = [
+                (
+                    (cos_1 - film_ior * cos_2) / (cos_1 + film_ior * cos_2),
+                    (film_ior * cos_2 - ior * cos_3) / (film_ior * cos_2 + ior * cos_3),
+                ),
+                (
+                    (film_ior * cos_1 - cos_2) / (film_ior * cos_1 + cos_2),
+                    (ior * cos_2 - film_ior * cos_3) / (ior * cos_2 + film_ior * cos_3),
+                ),
+            ];
+            for (r_12, r_23) in polarizations {
+                let cross = 2.0 * r_12 * r_23 * f32::cos(phase);
+                let numerator = r_12 * r_12 + r_23 * r_23 + cross;
+                let denominator = 1.0 + r_12 * r_12 * r_23 * r_23 + cross;
+                reflectance.data[i] += 0.5 * (numerator / denominator).clamp(0.0, 1.0);
+            }
+        }
+        return reflectance;
+    }
+
+    fn schlick(f0: Vec3f, cos_theta: f32) -> Vec3f {
+        return f0 + (Vec3f::from(1.0) - f0) * Self::schlick_weight(cos_theta);
+    }
+
+    fn schlick_weight(cos_theta: f32) -> f32 {
+        return f32::powi(1.0 - cos_theta.clamp(0.0, 1.0), 5);
+    }
+
+    /// Unpolarized Fresnel reflectance, `eta` is the transmitted over the incident index of refraction
+    fn fresnel_dielectric(cos_theta_i: f32, eta: f32) -> f32 {
+        let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+        let sin_2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+        if sin_2_theta_t >= 1.0 {
+            return 1.0;
+        }
+        let cos_theta_t = f32::sqrt(1.0 - sin_2_theta_t);
+        let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+        let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+        return 0.5 * (parallel * parallel + perpendicular * perpendicular);
+    }
+
+    /// Unpolarized Fresnel reflectance of a conductor seen from air
+    fn fresnel_conductor(cos_theta_i: f32, conductor: ComplexIor) -> Vec3f {
+        let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+        let cos_2 = cos_theta_i * cos_theta_i;
+        let sin_2 = 1.0 - cos_2;
+        let mut reflectance = Vec3f::from(0.0);
+        for i in 0..3 {
+            let eta_2 = conductor.eta.data[i] * conductor.eta.data[i];
+            let k_2 = conductor.k.data[i] * conductor.k.data[i];
+
+            let t_0 = eta_2 - k_2 - sin_2;
+            let a_2_plus_b_2 = f32::sqrt(t_0 * t_0 + 4.0 * eta_2 * k_2);
+            let a = f32::sqrt(f32::max(0.5 * (a_2_plus_b_2 + t_0), 0.0));
+            let t_1 = a_2_plus_b_2 + cos_2;
+            let t_2 = 2.0 * cos_theta_i * a;
+            let perpendicular = (t_1 - t_2) / (t_1 + t_2);
+
+            let t_3 = cos_2 * a_2_plus_b_2 + sin_2 * sin_2;
+            let t_4 = t_2 * sin_2;
+            let parallel = perpendicular * (t_3 - t_4) / (t_3 + t_4);
+            reflectance.data[i] = 0.5 * (parallel + perpendicular);
+        }
+        return reflectance;
+    }
+
+    fn ggx_d(h: Vec3f, alpha: [f32; 2]) -> f32 {
+        let x = h.x() / alpha[0];
+        let y = h.y() / alpha[1];
+        let denominator = x * x + y * y + h.z() * h.z();
+        return 1.0 / (std::f32::consts::PI * alpha[0] * alpha[1] * denominator * denominator);
+    }
+
+    fn smith_lambda(w: Vec3f, alpha: [f32; 2]) -> f32 {
+        let x = w.x() * alpha[0];
+        let y = w.y() * alpha[1];
+        let alpha_2_tan_2 = (x * x + y * y) / (w.z() * w.z());
+        return (f32::sqrt(1.0 + alpha_2_tan_2) - 1.0) * 0.5;
+    }
+
+    fn smith_g1(w: Vec3f, alpha: [f32; 2]) -> f32 {
+        return 1.0 / (1.0 + Self::smith_lambda(w, alpha));
+    }
+
+    /// Height correlated masking and shadowing
+    fn smith_g2(wo: Vec3f, wi: Vec3f, alpha: [f32; 2]) -> f32 {
+        return 1.0 / (1.0 + Self::smith_lambda(wo, alpha) + Self::smith_lambda(wi, alpha));
+    }
+
+    /// Samples a microfacet normal proportional to its visible area from `wo`, Heitz 2018
+    fn sample_visible_normal(wo: Vec3f, alpha: [f32; 2], u_1: f32, u_2: f32) -> Vec3f {
+        let v = Vec3f::new(alpha[0] * wo.x(), alpha[1] * wo.y(), wo.z()).normalized();
+
+        let length_2 = v.x() * v.x() + v.y() * v.y();
+        let t_1 = if length_2 > 0.0 {
+            Vec3f::new(-v.y(), v.x(), 0.0) / f32::sqrt(length_2)
+        } else {
+            Vec3f::new(1.0, 0.0, 0.0)
+        };
+        let t_2 = Vec3f::cross(v, t_1);
+
+        let r = f32::sqrt(u_1);
+        let phi = std::f32::consts::TAU * u_2;
+        let p_1 = r * f32::cos(phi);
+        let s = 0.5 * (1.0 + v.z());
+        let p_2 = (1.0 - s) * f32::sqrt(1.0 - p_1 * p_1) + s * r * f32::sin(phi);
+        let n = t_1 * p_1 + t_2 * p_2 + v * f32::sqrt(f32::max(1.0 - p_1 * p_1 - p_2 * p_2, 0.0));
+
+        return Vec3f::new(alpha[0] * n.x(), alpha[1] * n.y(), f32::max(n.z(), 0.0)).normalized();
+    }
+
+    fn to_local(&self, w: Vec3f) -> Vec3f {
+        return Vec3f::new(
+            Vec3f::dot(w, self.tangent),
+            Vec3f::dot(w, self.bitangent),
+            Vec3f::dot(w, self.normal),
+        );
+    }
+
+    fn to_world(&self, w: Vec3f) -> Vec3f {
+        return self.tangent * w.x() + self.bitangent * w.y() + self.normal * w.z();
+    }
+}
@@ -0,0 +1,205 @@
+use crate::vector::Vec3f;
+
+const ACES_INPUT: [[f32; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+const ACES_OUTPUT: [[f32; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+const AGX_INPUT: [[f32; 3]; 3] = [
+    [0.8424791, 0.0784336, 0.0792237],
+    [0.0423282, 0.8784686, 0.0791661],
+    [0.0423757, 0.0784336, 0.8791430],
+];
+const AGX_OUTPUT: [[f32; 3]; 3] = [
+    [1.1968790, -0.0980209, -0.0990297],
+    [-0.0528969, 1.1519031, -0.0989612],
+    [-0.0529716, -0.0980435, 1.1510737],
+];
+/// Exposure range in stops covered by the AgX log encoding
+const AGX_MIN_EV: f32 = -12.47393;
+const AGX_MAX_EV: f32 = 4.026069;
+/// Color temperature in Kelvin that white balance leaves untouched
+pub const NEUTRAL_TEMPERATURE: f32 = 6500.0;
+
+/// Compresses linear radiance into the displayable 0.0 - 1.0 range
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum ToneMapping {
+    /// Values above 1.0 are clipped
+    #[default]
+    None,
+    /// Reinhard on luminance, keeps hues but desaturates slowly
+    Reinhard,
+    /// Stephen Hill's fit of the ACES reference and sRGB output transforms
+    AcesFitted,
+    /// Polynomial fit of the AgX base look, desaturates highlights towards white
+    AgX,
+}
+
+impl ToneMapping {
+    pub const ALL: [Self; 4] = [Self::None, Self::Reinhard, Self::AcesFitted, Self::AgX];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => return "None",
+            Self::Reinhard => return "Reinhard",
+            Self::AcesFitted => return "ACES fitted",
+            Self::AgX => return "AgX",
+        }
+    }
+
+    pub fn apply(self, color: Vec3f) -> Vec3f {
+        match self {
+            Self::None => return Vec3f::min(color, Vec3f::from(1.0)),
+            Self::Reinhard => {
+                let luminance = color.luminance();
+                if luminance <= 0.0 {
+                    return Vec3f::from(0.0);
+                }
+                return Vec3f::min(color / (1.0 + luminance), Vec3f::from(1.0));
+            }
+            Self::AcesFitted => {
+                let v = multiply(&ACES_INPUT, color);
+                let mut fitted = Vec3f::from(0.0);
+                for i in 0..3 {
+                    let a = v.data[i] * (v.data[i] + 0.0245786) - 0.000090537;
+                    let b = v.data[i] * (0.983729 * v.data[i] + 0.4329510) + 0.238081;
+                    fitted.data[i] = a / b;
+                }
+                return Vec3f::max(
+                    Vec3f::min(multiply(&ACES_OUTPUT, fitted), Vec3f::from(1.0)),
+                    Vec3f::from(0.0),
+                );
+            }
+            Self::AgX => {
+                let v = multiply(&AGX_INPUT, Vec3f::max(color, Vec3f::from(1e-10)));
+                let mut curve = Vec3f::from(0.0);
+                for i in 0..3 {
+                    let x = (f32::log2(v.data[i]).clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV)
+                        / (AGX_MAX_EV - AGX_MIN_EV);
+                    let x_2 = x * x;
+                    let x_4 = x_2 * x_2;
+                    curve.data[i] = 15.5 * x_4 * x_2 - 40.14 * x_4 * x + 31.96 * x_4
+                        - 6.868 * x_2 * x
+                        + 0.4298 * x_2
+                        + 0.1191 * x
+                        - 0.00232;
+                }
+                // The curve targets a 2.2 display, linearize so the output transform applies once
+                let display = multiply(&AGX_OUTPUT, curve);
+                let mut linear = Vec3f::from(0.0);
+                for i in 0..3 {
+                    linear.data[i] = f32::powf(display.data[i].clamp(0.0, 1.0), 2.2);
+                }
+                return linear;
+            }
+        }
+    }
+}
+
+/// How linear radiance is encoded before it is written to the image
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum OutputTransform {
+    #[default]
+    Srgb,
+    /// Pure 2.2 power curve
+    Gamma22,
+    /// Raw values clipped to 0.0 - 1.0
+    Linear,
+}
+
+impl OutputTransform {
+    pub const ALL: [Self; 3] = [Self::Srgb, Self::Gamma22, Self::Linear];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Srgb => return "sRGB",
+            Self::Gamma22 => return "Gamma 2.2",
+            Self::Linear => return "Linear",
+        }
+    }
+
+    pub fn apply(self, linear: Vec3f) -> Vec3f {
+        let mut encoded = Vec3f::from(0.0);
+        for i in 0..3 {
+            let value = f32::max(linear.data[i], 0.0);
+            encoded.data[i] = match self {
+                Self::Srgb => linear_to_srgb(value),
+                Self::Gamma22 => f32::powf(value, 1.0 / 2.2),
+                Self::Linear => value,
+            };
+        }
+        return encoded;
+    }
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        return value / 12.92;
+    }
+    return f32::powf((value + 0.055) / 1.055, 2.4);
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        return value * 12.92;
+    }
+    return 1.055 * f32::powf(value, 1.0 / 2.4) - 0.055;
+}
+
+pub fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vec3f {
+    if y <= 0.0 {
+        return Vec3f::from(0.0);
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    return Vec3f::new(
+        f32::max(3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z, 0.0),
+        f32::max(-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z, 0.0),
+        f32::max(0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z, 0.0),
+    );
+}
+
+/// Per channel gains that make a white surface lit by a blackbody of `temperature` Kelvin look neutral
+pub fn white_balance(temperature: f32) -> Vec3f {
+    let white = |temperature: f32| -> Vec3f {
+        let (x, y) = planckian_locus(temperature);
+        return xyy_to_rgb(x, y, 1.0);
+    };
+    return white(NEUTRAL_TEMPERATURE) / Vec3f::max(white(temperature), Vec3f::from(1e-4));
+}
+
+/// Chromaticity of a blackbody, Kang et al. 2002 approximation valid from 1667 K to 25000 K
+fn planckian_locus(temperature: f32) -> (f32, f32) {
+    let t = temperature.clamp(1667.0, 25000.0);
+    let t_2 = t * t;
+    let t_3 = t_2 * t;
+    let x = if t < 4000.0 {
+        -0.2661239e9 / t_3 - 0.2343589e6 / t_2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t_3 + 2.1070379e6 / t_2 + 0.2226347e3 / t + 0.240390
+    };
+    let x_2 = x * x;
+    let x_3 = x_2 * x;
+    let y = if t < 2222.0 {
+        -1.1063814 * x_3 - 1.34811020 * x_2 + 2.18555832 * x - 0.20219683
+    } else if t < 4000.0 {
+        -0.9549476 * x_3 - 1.37418593 * x_2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x_3 - 5.87338670 * x_2 + 3.75112997 * x - 0.37001483
+    };
+    return (x, y);
+}
+
+fn multiply(matrix: &[[f32; 3]; 3], v: Vec3f) -> Vec3f {
+    let mut result = Vec3f::from(0.0);
+    for row in 0..3 {
+        result.data[row] =
+            matrix[row][0] * v.data[0] + matrix[row][1] * v.data[1] + matrix[row][2] * v.data[2];
+    }
+    return result;
+}
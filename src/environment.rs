@@ -0,0 +1,177 @@
+use crate::loader::hdr::{self, HDR};
+use crate::vector::{Vec3Swizzles, Vec3f};
+use crate::{log_error, log_info, log_warning};
+use std::io::Read;
+
+/// Equirectangular environment map used to light rays that leave the scene
+#[derive(Clone, Default)]
+pub struct Environment {
+    pub width: usize,
+    pub height: usize,
+    pub pixel_data: Vec<Vec3f>,
+    /// Cumulative distribution over rows, `height + 1` entries
+    marginal_cdf: Vec<f32>,
+    /// Cumulative distribution within each row, `width + 1` entries per row
+    conditional_cdf: Vec<f32>,
+}
+
+impl Environment {
+    pub fn load(path: &str) -> Option<Self> {
+        if !std::fs::exists(path).unwrap() {
+            log_error!("Could not find environment map at path: '{}'", path);
+            return None;
+        }
+
+        let mut signature: Vec<u8> = Vec::new();
+        std::fs::File::open(path)
+            .unwrap()
+            .take(hdr::SIGNATURE.len() as u64)
+            .read_to_end(&mut signature)
+            .unwrap();
+
+        if signature.starts_with(&hdr::SIGNATURE) {
+            let start_time = std::time::Instant::now();
+            let environment: Self = HDR::load(path).into();
+            log_info!(
+                "'{}' took {} ms to load\n",
+                path,
+                start_time.elapsed().as_millis()
+            );
+            return Some(environment);
+        } else {
+            let format = path.split(".").last().unwrap();
+            log_warning!(
+                "Unsupported environment map format '{}' at path '{}'",
+                format,
+                path
+            );
+            return None;
+        }
+    }
+
+    /// Maps a world space direction to equirectangular texture coordinates.
+    /// The center of the map faces -Z, `rotation` turns the map around +Y in radians.
+    pub fn direction_to_uv(direction: Vec3f, rotation: f32) -> [f32; 2] {
+        let phi = f32::atan2(direction.x(), -direction.z()) - rotation;
+        let theta = f32::asin(direction.y().clamp(-1.0, 1.0));
+        let u = (phi / std::f32::consts::TAU + 0.5).rem_euclid(1.0);
+        let v = theta / std::f32::consts::PI + 0.5;
+        return [u, v];
+    }
+
+    pub fn uv_to_direction(uv: [f32; 2], rotation: f32) -> Vec3f {
+        let phi = (uv[0] - 0.5) * std::f32::consts::TAU + rotation;
+        let theta = (uv[1] - 0.5) * std::f32::consts::PI;
+        return Vec3f::new(
+            f32::cos(theta) * f32::sin(phi),
+            f32::sin(theta),
+            -f32::cos(theta) * f32::cos(phi),
+        );
+    }
+
+    pub fn radiance(&self, direction: Vec3f, rotation: f32) -> Vec3f {
+        let (x, y) = self.texel_at(Self::direction_to_uv(direction, rotation));
+        return self.pixel_data[x + y * self.width];
+    }
+
+    /// Picks a direction proportional to the luminance of the map.
+    /// Returns the direction and its solid angle probability density.
+    pub fn sample(&self, rotation: f32, rng_state: &mut u32) -> (Vec3f, f32) {
+        let y = Self::sample_cdf(&self.marginal_cdf, Vec3f::rand_f32(rng_state));
+        let row_cdf = &self.conditional_cdf[y * (self.width + 1)..(y + 1) * (self.width + 1)];
+        let x = Self::sample_cdf(row_cdf, Vec3f::rand_f32(rng_state));
+
+        let uv = [
+            (x as f32 + Vec3f::rand_f32(rng_state)) / self.width as f32,
+            (y as f32 + Vec3f::rand_f32(rng_state)) / self.height as f32,
+        ];
+        let direction = Self::uv_to_direction(uv, rotation);
+        return (direction, self.texel_pdf(x, y, uv[1]));
+    }
+
+    /// Solid angle probability density of `sample` returning `direction`
+    pub fn pdf(&self, direction: Vec3f, rotation: f32) -> f32 {
+        let uv = Self::direction_to_uv(direction, rotation);
+        let (x, y) = self.texel_at(uv);
+        return self.texel_pdf(x, y, uv[1]);
+    }
+
+    fn texel_at(&self, uv: [f32; 2]) -> (usize, usize) {
+        let x = usize::min((uv[0] * self.width as f32) as usize, self.width - 1);
+        let y = usize::min((uv[1] * self.height as f32) as usize, self.height - 1);
+        return (x, y);
+    }
+
+    fn texel_pdf(&self, x: usize, y: usize, v: f32) -> f32 {
+        let row_start = y * (self.width + 1);
+        let row_pdf = self.marginal_cdf[y + 1] - self.marginal_cdf[y];
+        let column_pdf =
+            self.conditional_cdf[row_start + x + 1] - self.conditional_cdf[row_start + x];
+        let uv_pdf = row_pdf * column_pdf * (self.width * self.height) as f32;
+
+        // Texels near the poles cover less solid angle than texels at the horizon
+        let cos_theta = f32::cos((v - 0.5) * std::f32::consts::PI);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        return uv_pdf / (2.0 * std::f32::consts::PI * std::f32::consts::PI * cos_theta);
+    }
+
+    /// Binary searches the last CDF entry that is not larger than `value`
+    fn sample_cdf(cdf: &[f32], value: f32) -> usize {
+        let index = cdf.partition_point(|entry| *entry <= value);
+        return index.clamp(1, cdf.len() - 1) - 1;
+    }
+
+    fn build_distribution(&mut self) {
+        self.conditional_cdf = vec![0.0; self.height * (self.width + 1)];
+        self.marginal_cdf = vec![0.0; self.height + 1];
+
+        let mut row_sums: Vec<f32> = vec![0.0; self.height];
+        for y in 0..self.height {
+            let v = (y as f32 + 0.5) / self.height as f32;
+            let cos_theta = f32::cos((v - 0.5) * std::f32::consts::PI);
+
+            let row_start = y * (self.width + 1);
+            for x in 0..self.width {
+                let weight = self.pixel_data[x + y * self.width].luminance() * cos_theta;
+                self.conditional_cdf[row_start + x + 1] =
+                    self.conditional_cdf[row_start + x] + weight;
+            }
+            row_sums[y] = self.conditional_cdf[row_start + self.width];
+
+            for x in 1..=self.width {
+                if row_sums[y] > 0.0 {
+                    self.conditional_cdf[row_start + x] /= row_sums[y];
+                } else {
+                    self.conditional_cdf[row_start + x] = x as f32 / self.width as f32;
+                }
+            }
+        }
+
+        for y in 0..self.height {
+            self.marginal_cdf[y + 1] = self.marginal_cdf[y] + row_sums[y];
+        }
+        let total = self.marginal_cdf[self.height];
+        for y in 1..=self.height {
+            if total > 0.0 {
+                self.marginal_cdf[y] /= total;
+            } else {
+                self.marginal_cdf[y] = y as f32 / self.height as f32;
+            }
+        }
+    }
+}
+
+impl From<HDR> for Environment {
+    fn from(hdr: HDR) -> Self {
+        let mut environment = Self {
+            width: hdr.width as usize,
+            height: hdr.height as usize,
+            pixel_data: hdr.pixel_data.into_iter().map(Vec3f::from).collect(),
+            ..Default::default()
+        };
+        environment.build_distribution();
+        return environment;
+    }
+}
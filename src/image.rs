@@ -1,3 +1,4 @@
+use crate::vector::Vec3f;
 use crate::{log_error, log_info};
 use std::io::Write;
 
@@ -7,6 +8,8 @@ pub struct Image {
     pub width: usize,
     pub height: usize,
     pub bytes: Vec<u8>,
+    /// Linear radiance the bytes were developed from
+    pub radiance: Vec<Vec3f>,
 }
 
 #[derive(Default)]
@@ -22,6 +25,7 @@ impl Image {
             width,
             height,
             bytes: Vec::new(),
+            radiance: Vec::new(),
         };
     }
 
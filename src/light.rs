@@ -0,0 +1,468 @@
+use crate::log_info;
+use crate::scene::{Scene, Triangle};
+use crate::vector::Vec3f;
+
+/// Binary tree over the emissive triangles of a scene, walked from the root to pick a light
+/// roughly proportional to how much it contributes at a shading point
+#[derive(Clone, Default)]
+pub struct LightTree {
+    nodes: Vec<LightNode>,
+    /// Leaf node for every triangle of the scene, -1 when it does not emit
+    leaf_ids: Vec<i32>,
+}
+
+#[derive(Clone, Copy)]
+struct LightNode {
+    bounds_min: Vec3f,
+    bounds_max: Vec3f,
+    power: f32,
+    /// Normal of the triangle in a leaf, zero for inner nodes
+    normal: Vec3f,
+    /// Index of the first child, the second one follows it. 0 for leaves
+    children_id: usize,
+    parent_id: usize,
+    /// Index into `Scene::tris` for leaves
+    tri_id: usize,
+}
+
+impl Default for LightNode {
+    fn default() -> Self {
+        return Self {
+            bounds_min: Vec3f::from(f32::MAX),
+            bounds_max: Vec3f::from(-f32::MAX),
+            power: 0.0,
+            normal: Vec3f::from(0.0),
+            children_id: 0,
+            parent_id: 0,
+            tri_id: 0,
+        };
+    }
+}
+
+impl LightTree {
+    /// Has to run after the BVH is built, building it reorders the triangles
+    pub fn build(scene: &Scene) -> Self {
+        let start_time = std::time::Instant::now();
+
+        let mut tree = Self {
+            nodes: Vec::new(),
+            leaf_ids: vec![-1; scene.tris.len()],
+        };
+
+        let mut leaves = Vec::new();
+        for (tri_id, tri) in scene.tris.iter().enumerate() {
+            let power = Self::mean_emission(tri, scene).luminance() * tri.area();
+            if power <= 0.0 {
+                continue;
+            }
+
+            let mut leaf = LightNode {
+                power,
+                normal: tri.geometric_normal(),
+                tri_id,
+                ..LightNode::default()
+            };
+            for vertex in &tri.vertices {
+                leaf.bounds_min = Vec3f::min(leaf.bounds_min, Vec3f::from(vertex.position));
+                leaf.bounds_max = Vec3f::max(leaf.bounds_max, Vec3f::from(vertex.position));
+            }
+            leaves.push(leaf);
+        }
+
+        if !leaves.is_empty() {
+            tree.nodes.push(LightNode::default());
+            tree.split_node(0, 0, &mut leaves);
+        }
+
+        log_info!("Light tree statistics");
+        log_info!("- Build time:  {} ms", start_time.elapsed().as_millis());
+        log_info!("- Total nodes: {}", tree.nodes.len());
+        log_info!("- Lights:      {}\n", leaves.len());
+
+        return tree;
+    }
+
+    /// Average radiance emitted across a triangle. Textured emission ignores the emission color,
+    /// same as when a ray hits it, and is averaged over the centers of a grid of smaller triangles
+    /// with lookups as wide as one of them.
+    fn mean_emission(tri: &Triangle, scene: &Scene) -> Vec3f {
+        const GRID_SIZE: usize = 4;
+
+        let material = &scene.materials[tri.material_id];
+        if material.emission_tex_id == -1 {
+            return material.emission;
+        }
+        let texture = &scene.textures[material.emission_tex_id as usize];
+
+        let [u_0, v_0] = tri.vertices[0].tex_coord;
+        let [u_1, v_1] = tri.vertices[1].tex_coord;
+        let [u_2, v_2] = tri.vertices[2].tex_coord;
+        let uv_area = f32::abs((u_1 - u_0) * (v_2 - v_0) - (u_2 - u_0) * (v_1 - v_0)) * 0.5;
+        let footprint = f32::sqrt(2.0 * uv_area) / GRID_SIZE as f32;
+
+        let mut sum = Vec3f::from(0.0);
+        let mut count = 0;
+        for i in 0..GRID_SIZE {
+            for j in 0..GRID_SIZE - i {
+                // Every cell holds an upright triangle and, away from the diagonal edge,
+                // an upside down one
+                let mut centers = vec![[i as f32 + 1.0 / 3.0, j as f32 + 1.0 / 3.0]];
+                if i + j + 1 < GRID_SIZE {
+                    centers.push([i as f32 + 2.0 / 3.0, j as f32 + 2.0 / 3.0]);
+                }
+                for [a, b] in centers {
+                    let b_1 = a / GRID_SIZE as f32;
+                    let b_2 = b / GRID_SIZE as f32;
+                    let b_0 = 1.0 - b_1 - b_2;
+                    let uv = [
+                        u_0 * b_0 + u_1 * b_1 + u_2 * b_2,
+                        v_0 * b_0 + v_1 * b_1 + v_2 * b_2,
+                    ];
+                    sum += texture.color_at(uv, &material.emission_sampler, footprint);
+                    count += 1;
+                }
+            }
+        }
+        return sum / count as f32;
+    }
+
+    /// Turns `nodes[index]` into the parent of `leaves`, splitting them in half along the
+    /// longest axis of their centers until every leaf node holds a single triangle
+    fn split_node(&mut self, index: usize, parent_id: usize, leaves: &mut [LightNode]) {
+        if leaves.len() == 1 {
+            self.nodes[index] = LightNode {
+                parent_id,
+                ..leaves[0]
+            };
+            self.leaf_ids[leaves[0].tri_id] = index as i32;
+            return;
+        }
+
+        let mut node = LightNode {
+            parent_id,
+            ..LightNode::default()
+        };
+        let mut centers_min = Vec3f::from(f32::MAX);
+        let mut centers_max = Vec3f::from(-f32::MAX);
+        for leaf in leaves.iter() {
+            node.bounds_min = Vec3f::min(node.bounds_min, leaf.bounds_min);
+            node.bounds_max = Vec3f::max(node.bounds_max, leaf.bounds_max);
+            node.power += leaf.power;
+            centers_min = Vec3f::min(centers_min, leaf.center());
+            centers_max = Vec3f::max(centers_max, leaf.center());
+        }
+
+        let extent = centers_max - centers_min;
+        let mut split_axis = 0;
+        for axis in 1..3 {
+            if extent.data[axis] > extent.data[split_axis] {
+                split_axis = axis;
+            }
+        }
+        let middle = leaves.len() / 2;
+        leaves.select_nth_unstable_by(middle, |a, b| {
+            a.center().data[split_axis].total_cmp(&b.center().data[split_axis])
+        });
+
+        node.children_id = self.nodes.len();
+        self.nodes[index] = node;
+        self.nodes.push(LightNode::default());
+        self.nodes.push(LightNode::default());
+
+        let (left, right) = leaves.split_at_mut(middle);
+        self.split_node(node.children_id, index, left);
+        self.split_node(node.children_id + 1, index, right);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.nodes.is_empty();
+    }
+
+    /// Picks an emissive triangle to light `point`, returns its index into `Scene::tris`
+    /// and the probability of picking it
+    pub fn sample(&self, point: Vec3f, rng_state: &mut u32) -> (usize, f32) {
+        let mut index = 0;
+        let mut probability = 1.0;
+        while self.nodes[index].children_id != 0 {
+            let children_id = self.nodes[index].children_id;
+            let left_probability = self.left_probability(children_id, point);
+            if Vec3f::rand_f32(rng_state) < left_probability {
+                index = children_id;
+                probability *= left_probability;
+            } else {
+                index = children_id + 1;
+                probability *= 1.0 - left_probability;
+            }
+        }
+        return (self.nodes[index].tri_id, probability);
+    }
+
+    /// Probability of `sample` picking the triangle `tri_id` to light `point`
+    pub fn probability(&self, point: Vec3f, tri_id: usize) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let leaf_id = self.leaf_ids[tri_id];
+        if leaf_id == -1 {
+            return 0.0;
+        }
+
+        let mut index = leaf_id as usize;
+        let mut probability = 1.0;
+        while index != 0 {
+            let children_id = self.nodes[self.nodes[index].parent_id].children_id;
+            let left_probability = self.left_probability(children_id, point);
+            if index == children_id {
+                probability *= left_probability;
+            } else {
+                probability *= 1.0 - left_probability;
+            }
+            index = self.nodes[index].parent_id;
+        }
+        return probability;
+    }
+
+    /// Chance of descending into the first of two siblings, by their estimated contribution
+    fn left_probability(&self, children_id: usize, point: Vec3f) -> f32 {
+        let left = self.nodes[children_id].importance(point);
+        let right = self.nodes[children_id + 1].importance(point);
+        if left + right <= 0.0 {
+            return 0.5;
+        }
+        return left / (left + right);
+    }
+}
+
+impl LightNode {
+    fn center(&self) -> Vec3f {
+        return (self.bounds_min + self.bounds_max) * 0.5;
+    }
+
+    /// Power over squared distance, with the distance kept from falling below the size of the
+    /// bounds so points close to or inside them do not favour one light too strongly.
+    /// Emission is two-sided, so no light faces away from a point. Leaves are also weighted by
+    /// how squarely their triangle faces it, bounded over the sphere around them so no part of
+    /// the triangle is underestimated. Inner nodes mix orientations and skip this.
+    fn importance(&self, point: Vec3f) -> f32 {
+        let half_extent = (self.bounds_max - self.bounds_min) * 0.5;
+        let radius_squared = Vec3f::dot(half_extent, half_extent);
+        let offset = self.center() - point;
+        let distance_squared = Vec3f::dot(offset, offset);
+        if distance_squared <= radius_squared {
+            if radius_squared <= 0.0 {
+                return self.power;
+            }
+            return self.power / radius_squared;
+        }
+
+        let mut cos_bound = 1.0;
+        if self.children_id == 0 {
+            let distance = f32::sqrt(distance_squared);
+            let cos_theta = f32::abs(Vec3f::dot(self.normal, offset / distance));
+            let sin_spread = f32::sqrt(radius_squared) / distance;
+            let cos_spread = f32::sqrt(f32::max(1.0 - sin_spread * sin_spread, 0.0));
+            if cos_theta < cos_spread {
+                // Normal and direction are further apart than the sphere spreads,
+                // the closest direction within it is still that much further away
+                let sin_theta = f32::sqrt(f32::max(1.0 - cos_theta * cos_theta, 0.0));
+                cos_bound = cos_theta * cos_spread + sin_theta * sin_spread;
+            }
+        }
+        return self.power * cos_bound / distance_squared;
+    }
+}
+
+/// Light source that is not part of the scene geometry
+#[derive(Clone, Copy)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3f,
+    /// Radiant intensity of point and spot lights, irradiance of directional lights
+    /// and radiance of area lights
+    pub intensity: f32,
+}
+
+#[derive(Clone, Copy)]
+pub enum LightKind {
+    Point {
+        position: Vec3f,
+    },
+    /// Point light limited to a cone, fading out between the inner and outer angle in radians
+    Spot {
+        position: Vec3f,
+        direction: Vec3f,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+    /// Infinitely far away light arriving from the same direction everywhere,
+    /// `direction` is the way the light travels
+    Directional {
+        direction: Vec3f,
+    },
+    /// Parallelogram spanned by two edges from a corner, emitting towards `edge_u x edge_v`
+    Quad {
+        corner: Vec3f,
+        edge_u: Vec3f,
+        edge_v: Vec3f,
+    },
+}
+
+pub struct LightSample {
+    /// Unit direction from the shading point towards the light
+    pub direction: Vec3f,
+    pub distance: f32,
+    /// Radiance arriving at the shading point, point like lights include their falloff
+    pub radiance: Vec3f,
+    /// Solid angle density, 1.0 for lights that can only be reached by sampling them
+    pub pdf: f32,
+    pub is_delta: bool,
+}
+
+impl LightKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Point { .. } => return "Point",
+            Self::Spot { .. } => return "Spot",
+            Self::Directional { .. } => return "Directional",
+            Self::Quad { .. } => return "Area",
+        }
+    }
+}
+
+impl Light {
+    pub fn sample(&self, point: Vec3f, rng_state: &mut u32) -> Option<LightSample> {
+        let emitted = self.color * self.intensity;
+        match self.kind {
+            LightKind::Point { position } => {
+                return Self::point_sample(point, position, emitted);
+            }
+            LightKind::Spot {
+                position,
+                direction,
+                inner_angle,
+                outer_angle,
+            } => {
+                let mut sample = Self::point_sample(point, position, emitted)?;
+                let cos_theta = Vec3f::dot(sample.direction.reversed(), direction.normalized());
+                let cos_inner = f32::cos(inner_angle);
+                let cos_outer = f32::cos(f32::max(outer_angle, inner_angle));
+                let falloff = if cos_inner > cos_outer {
+                    let t = ((cos_theta - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0);
+                    t * t * (3.0 - 2.0 * t)
+                } else if cos_theta >= cos_inner {
+                    1.0
+                } else {
+                    0.0
+                };
+                if falloff <= 0.0 {
+                    return None;
+                }
+                sample.radiance *= falloff;
+                return Some(sample);
+            }
+            LightKind::Directional { direction } => {
+                return Some(LightSample {
+                    direction: direction.normalized().reversed(),
+                    distance: f32::MAX,
+                    radiance: emitted,
+                    pdf: 1.0,
+                    is_delta: true,
+                });
+            }
+            LightKind::Quad {
+                corner,
+                edge_u,
+                edge_v,
+            } => {
+                let position = corner
+                    + edge_u * Vec3f::rand_f32(rng_state)
+                    + edge_v * Vec3f::rand_f32(rng_state);
+                let to_light = position - point;
+                let distance = to_light.length();
+                if distance <= 0.0 {
+                    return None;
+                }
+                let direction = to_light / distance;
+                let pdf = Self::quad_pdf(edge_u, edge_v, direction, distance);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                return Some(LightSample {
+                    direction,
+                    distance,
+                    radiance: emitted,
+                    pdf,
+                    is_delta: false,
+                });
+            }
+        }
+    }
+
+    /// Finds where a ray crosses an area light closer than `max_distance`.
+    /// Returns the distance, the radiance towards the ray and the solid angle density
+    /// of `sample` picking the same direction, `None` for the other kinds of lights.
+    pub fn intersect(
+        &self,
+        origin: Vec3f,
+        direction: Vec3f,
+        max_distance: f32,
+    ) -> Option<(f32, Vec3f, f32)> {
+        let LightKind::Quad {
+            corner,
+            edge_u,
+            edge_v,
+        } = self.kind
+        else {
+            return None;
+        };
+
+        let normal = Vec3f::cross(edge_u, edge_v);
+        let denominator = Vec3f::dot(normal, direction);
+        // Only the front side emits
+        if denominator >= 0.0 {
+            return None;
+        }
+        let distance = Vec3f::dot(normal, corner - origin) / denominator;
+        if distance <= 0.0 || distance >= max_distance {
+            return None;
+        }
+
+        let offset = origin + direction * distance - corner;
+        let u = Vec3f::dot(Vec3f::cross(offset, edge_v), normal) / Vec3f::dot(normal, normal);
+        let v = Vec3f::dot(Vec3f::cross(edge_u, offset), normal) / Vec3f::dot(normal, normal);
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        let pdf = Self::quad_pdf(edge_u, edge_v, direction, distance);
+        return Some((distance, self.color * self.intensity, pdf));
+    }
+
+    fn point_sample(point: Vec3f, position: Vec3f, emitted: Vec3f) -> Option<LightSample> {
+        let to_light = position - point;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        return Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: emitted / (distance * distance),
+            pdf: 1.0,
+            is_delta: true,
+        });
+    }
+
+    /// Converts the uniform area density of a quad to solid angle, zero when seen from behind
+    fn quad_pdf(edge_u: Vec3f, edge_v: Vec3f, direction: Vec3f, distance: f32) -> f32 {
+        let normal = Vec3f::cross(edge_u, edge_v);
+        let area = normal.length();
+        if area <= 0.0 {
+            return 0.0;
+        }
+        let cos_theta = -Vec3f::dot(normal / area, direction);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        return distance * distance / (cos_theta * area);
+    }
+}
@@ -1,2 +1,7 @@
 pub mod bmp;
+pub mod hdr;
+pub mod inflate;
+pub mod jpeg;
+pub mod lights;
 pub mod obj;
+pub mod png;
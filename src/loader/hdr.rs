@@ -0,0 +1,165 @@
+use std::io::{BufRead, Read};
+
+pub const SIGNATURE: [u8; 2] = [b'#', b'?'];
+
+pub struct HDR {
+    pub width: i32,
+    pub height: i32,
+    pub pixel_data: Vec<[f32; 3]>,
+}
+
+impl HDR {
+    pub fn load(path: &str) -> Self {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if !line.starts_with("#?RADIANCE") && !line.starts_with("#?RGBE") {
+            panic!("File identifier of file '{}' is incorrect!", path);
+        }
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                panic!("Radiance HDR header of file '{}' is not terminated!", path);
+            }
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                break;
+            }
+            if let Some(format) = trimmed.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    panic!("Unsupported Radiance HDR pixel format '{}'!", format);
+                }
+            }
+        }
+
+        // Resolution string, e.g. "-Y 512 +X 1024" for top to bottom rows
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let resolution: Vec<&str> = line.split_whitespace().collect();
+        if resolution.len() != 4 || resolution[2] != "+X" {
+            panic!("Unsupported Radiance HDR orientation '{}'!", line.trim());
+        }
+        let top_to_bottom = match resolution[0] {
+            "-Y" => true,
+            "+Y" => false,
+            _ => panic!("Unsupported Radiance HDR orientation '{}'!", line.trim()),
+        };
+        let height: usize = resolution[1].parse().unwrap();
+        let width: usize = resolution[3].parse().unwrap();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+
+        let mut offset: usize = 0;
+        let mut scanlines: Vec<Vec<[u8; 4]>> = Vec::new();
+        scanlines.reserve_exact(height);
+        for _ in 0..height {
+            scanlines.push(Self::read_scanline(&bytes, &mut offset, width));
+        }
+
+        if top_to_bottom {
+            scanlines.reverse();
+        }
+
+        let mut pixel_data: Vec<[f32; 3]> = Vec::new();
+        pixel_data.reserve_exact(width * height);
+        for scanline in scanlines {
+            for rgbe in scanline {
+                pixel_data.push(Self::rgbe_to_float(rgbe));
+            }
+        }
+
+        return HDR {
+            width: width as i32,
+            height: height as i32,
+            pixel_data,
+        };
+    }
+
+    fn read_scanline(bytes: &[u8], offset: &mut usize, width: usize) -> Vec<[u8; 4]> {
+        let mut scanline: Vec<[u8; 4]> = vec![[0; 4]; width];
+        let next = |offset: &mut usize| -> u8 {
+            let Some(byte) = bytes.get(*offset) else {
+                panic!("Radiance HDR pixel data is truncated!");
+            };
+            *offset += 1;
+            return *byte;
+        };
+
+        let is_adaptive_rle = (8..32768).contains(&width)
+            && bytes.get(*offset) == Some(&2)
+            && bytes.get(*offset + 1) == Some(&2)
+            && bytes.get(*offset + 2).is_some_and(|byte| byte & 0x80 == 0);
+
+        if is_adaptive_rle {
+            let encoded_width =
+                ((bytes[*offset + 2] as usize) << 8) | bytes[*offset + 3] as usize;
+            if encoded_width != width {
+                panic!("Radiance HDR scanline width does not match the image width!");
+            }
+            *offset += 4;
+
+            // Each channel is run length encoded separately
+            for channel in 0..4 {
+                let mut x: usize = 0;
+                while x < width {
+                    let count = next(offset) as usize;
+                    let run = if count > 128 { count - 128 } else { count };
+                    if x + run > width {
+                        panic!("Radiance HDR run overflows the scanline!");
+                    }
+                    if count > 128 {
+                        let value = next(offset);
+                        for _ in 0..run {
+                            scanline[x][channel] = value;
+                            x += 1;
+                        }
+                    } else {
+                        for _ in 0..run {
+                            scanline[x][channel] = next(offset);
+                            x += 1;
+                        }
+                    }
+                }
+            }
+        } else {
+            // Flat pixels, possibly using the original (1, 1, 1, count) repeat encoding
+            let mut x: usize = 0;
+            let mut shift: usize = 0;
+            while x < width {
+                let rgbe = [next(offset), next(offset), next(offset), next(offset)];
+                if rgbe[0..3] == [1, 1, 1] && x > 0 {
+                    let run = (rgbe[3] as usize) << shift;
+                    if x + run > width {
+                        panic!("Radiance HDR run overflows the scanline!");
+                    }
+                    for _ in 0..run {
+                        scanline[x] = scanline[x - 1];
+                        x += 1;
+                    }
+                    shift += 8;
+                } else {
+                    scanline[x] = rgbe;
+                    x += 1;
+                    shift = 0;
+                }
+            }
+        }
+
+        return scanline;
+    }
+
+    fn rgbe_to_float(rgbe: [u8; 4]) -> [f32; 3] {
+        if rgbe[3] == 0 {
+            return [0.0; 3];
+        }
+        let scale = f32::powi(2.0, rgbe[3] as i32 - (128 + 8));
+        return [
+            rgbe[0] as f32 * scale,
+            rgbe[1] as f32 * scale,
+            rgbe[2] as f32 * scale,
+        ];
+    }
+}
@@ -0,0 +1,251 @@
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompresses a zlib stream (RFC 1950) wrapping raw DEFLATE data (RFC 1951).
+pub fn zlib_decompress(data: &[u8]) -> Vec<u8> {
+    if data.len() < 2 {
+        panic!("zlib stream is too short!");
+    }
+    let cmf = data[0];
+    let flg = data[1];
+    if cmf & 0x0F != 8 {
+        panic!("zlib compression method must be DEFLATE!");
+    }
+    if !((cmf as u16) << 8 | flg as u16).is_multiple_of(31) {
+        panic!("zlib header checksum is incorrect!");
+    }
+    if flg & 0x20 != 0 {
+        panic!("zlib preset dictionaries are not supported!");
+    }
+
+    return inflate(&data[2..]);
+}
+
+/// Decompresses raw DEFLATE data (RFC 1951).
+pub fn inflate(data: &[u8]) -> Vec<u8> {
+    let mut reader = BitReader::new(data);
+    let mut output: Vec<u8> = Vec::new();
+
+    loop {
+        let is_final = reader.bits(1) == 1;
+        match reader.bits(2) {
+            0 => {
+                reader.align_to_byte();
+                let len = reader.bits(16) as usize;
+                let nlen = reader.bits(16) as usize;
+                if len != !nlen & 0xFFFF {
+                    panic!("Stored DEFLATE block has a corrupt length!");
+                }
+                for _ in 0..len {
+                    output.push(reader.bits(8) as u8);
+                }
+            }
+            1 => {
+                let (lit_len, dist) = Huffman::fixed();
+                inflate_block(&mut reader, &mut output, &lit_len, &dist);
+            }
+            2 => {
+                let (lit_len, dist) = Huffman::dynamic(&mut reader);
+                inflate_block(&mut reader, &mut output, &lit_len, &dist);
+            }
+            _ => panic!("Invalid DEFLATE block type!"),
+        }
+
+        if is_final {
+            break;
+        }
+    }
+
+    return output;
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, lit_len: &Huffman, dist: &Huffman) {
+    loop {
+        let symbol = lit_len.decode(reader);
+        if symbol < 256 {
+            output.push(symbol as u8);
+        } else if symbol == 256 {
+            return;
+        } else {
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                panic!("Invalid DEFLATE length symbol!");
+            }
+            let length = LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol]) as usize;
+
+            let symbol = dist.decode(reader);
+            if symbol >= DIST_BASE.len() {
+                panic!("Invalid DEFLATE distance symbol!");
+            }
+            let distance = DIST_BASE[symbol] as usize + reader.bits(DIST_EXTRA[symbol]) as usize;
+            if distance > output.len() {
+                panic!("DEFLATE distance reaches before the start of the output!");
+            }
+
+            let start = output.len() - distance;
+            for i in 0..length {
+                output.push(output[start + i]);
+            }
+        }
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        return Self {
+            data,
+            position: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        };
+    }
+
+    /// Reads `count` bits (at most 16), least significant bit first.
+    fn bits(&mut self, count: u8) -> u32 {
+        while self.bit_count < count {
+            let Some(byte) = self.data.get(self.position) else {
+                panic!("Unexpected end of DEFLATE data!");
+            };
+            self.bit_buffer |= (*byte as u32) << self.bit_count;
+            self.position += 1;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u32 << count) - 1);
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        return value;
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+/// Canonical Huffman code stored as per-length code counts and symbols sorted by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for i in 1..=MAX_BITS {
+            offsets[i + 1] = offsets[i] + counts[i];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        return Self { counts, symbols };
+    }
+
+    fn fixed() -> (Self, Self) {
+        let mut lengths = [0u8; 288];
+        lengths[0..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..288].fill(8);
+        return (Self::new(&lengths), Self::new(&[5u8; 30]));
+    }
+
+    fn dynamic(reader: &mut BitReader) -> (Self, Self) {
+        let num_lit_len = reader.bits(5) as usize + 257;
+        let num_dist = reader.bits(5) as usize + 1;
+        let num_code_len = reader.bits(4) as usize + 4;
+
+        let mut code_lengths = [0u8; 19];
+        for i in 0..num_code_len {
+            code_lengths[CODE_LENGTH_ORDER[i]] = reader.bits(3) as u8;
+        }
+        let code_length_huffman = Self::new(&code_lengths);
+
+        let mut lengths: Vec<u8> = Vec::new();
+        lengths.reserve_exact(num_lit_len + num_dist);
+        while lengths.len() < num_lit_len + num_dist {
+            let symbol = code_length_huffman.decode(reader);
+            match symbol {
+                0..=15 => lengths.push(symbol as u8),
+                16 => {
+                    let Some(prev) = lengths.last().copied() else {
+                        panic!("DEFLATE code length repeat without a previous length!");
+                    };
+                    let repeat = 3 + reader.bits(2) as usize;
+                    lengths.resize(lengths.len() + repeat, prev);
+                }
+                17 => {
+                    let repeat = 3 + reader.bits(3) as usize;
+                    lengths.resize(lengths.len() + repeat, 0);
+                }
+                18 => {
+                    let repeat = 11 + reader.bits(7) as usize;
+                    lengths.resize(lengths.len() + repeat, 0);
+                }
+                _ => panic!("Invalid DEFLATE code length symbol!"),
+            }
+        }
+        if lengths.len() > num_lit_len + num_dist {
+            panic!("DEFLATE code lengths overflow the alphabet!");
+        }
+
+        return (
+            Self::new(&lengths[..num_lit_len]),
+            Self::new(&lengths[num_lit_len..]),
+        );
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> usize {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_BITS {
+            code |= reader.bits(1) as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return self.symbols[(index + (code - first)) as usize] as usize;
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        panic!("Invalid DEFLATE Huffman code!");
+    }
+}
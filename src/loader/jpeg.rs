@@ -0,0 +1,541 @@
+pub const SIGNATURE: [u8; 3] = [0xFF, 0xD8, 0xFF];
+
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27,
+    20, 13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58,
+    59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+pub struct JPEG {
+    pub width: i32,
+    pub height: i32,
+    pub pixel_data: Vec<[u8; 3]>,
+}
+
+#[derive(Clone, Default)]
+struct Component {
+    id: u8,
+    h_sampling: usize,
+    v_sampling: usize,
+    quant_table_id: usize,
+    dc_table_id: usize,
+    ac_table_id: usize,
+    dc_prediction: i32,
+    blocks_per_line: usize,
+    blocks_per_column: usize,
+    samples: Vec<u8>,
+}
+
+/// Huffman table expanded into a (length, code) -> symbol lookup
+#[derive(Clone, Default)]
+struct HuffmanTable {
+    max_code: [i32; 17],
+    val_offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8; 16], values: Vec<u8>) -> Self {
+        let mut table = Self {
+            max_code: [-1; 17],
+            val_offset: [0; 17],
+            values,
+        };
+        let mut code: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            if count > 0 {
+                table.val_offset[length] = index - code;
+                code += count;
+                index += count;
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        return table;
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> u8 {
+        let mut code: i32 = 0;
+        for length in 1..=16 {
+            code = (code << 1) | reader.bit() as i32;
+            if code <= self.max_code[length] {
+                return self.values[(code + self.val_offset[length]) as usize];
+            }
+        }
+        panic!("Invalid JPEG Huffman code!");
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        return Self {
+            data,
+            position,
+            bit_buffer: 0,
+            bit_count: 0,
+        };
+    }
+
+    /// Reads one bit of entropy coded data, removing stuffed zero bytes after 0xFF.
+    /// Running into a marker yields zero bits so the decoder can finish the interval.
+    fn bit(&mut self) -> u32 {
+        if self.bit_count == 0 {
+            let mut byte: u8 = 0;
+            if let Some(next) = self.data.get(self.position) {
+                if *next == 0xFF {
+                    if self.data.get(self.position + 1) == Some(&0x00) {
+                        byte = 0xFF;
+                        self.position += 2;
+                    }
+                } else {
+                    byte = *next;
+                    self.position += 1;
+                }
+            }
+            self.bit_buffer = byte as u32;
+            self.bit_count = 8;
+        }
+        self.bit_count -= 1;
+        return (self.bit_buffer >> self.bit_count) & 1;
+    }
+
+    fn bits(&mut self, count: u8) -> u32 {
+        let mut value: u32 = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.bit();
+        }
+        return value;
+    }
+
+    /// Sign extends a `size` bit magnitude category value
+    fn receive_extend(&mut self, size: u8) -> i32 {
+        if size == 0 {
+            return 0;
+        }
+        let value = self.bits(size) as i32;
+        if value < (1 << (size - 1)) {
+            return value - (1 << size) + 1;
+        } else {
+            return value;
+        }
+    }
+
+    /// Drops any buffered bits and skips the next RSTn marker.
+    fn restart(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+        while self.position + 1 < self.data.len() {
+            if self.data[self.position] == 0xFF && (0xD0..=0xD7).contains(&self.data[self.position + 1])
+            {
+                self.position += 2;
+                return;
+            }
+            self.position += 1;
+        }
+    }
+}
+
+impl JPEG {
+    pub fn load(path: &str) -> Self {
+        let bytes = std::fs::read(path).unwrap();
+        if bytes.len() < 2 || bytes[0..2] != SIGNATURE[0..2] {
+            panic!("File identifier of file '{}' is incorrect!", path);
+        }
+
+        let mut quant_tables: [[u16; 64]; 4] = [[1; 64]; 4];
+        let mut dc_tables: [HuffmanTable; 4] = Default::default();
+        let mut ac_tables: [HuffmanTable; 4] = Default::default();
+        let mut components: Vec<Component> = Vec::new();
+        let mut width: usize = 0;
+        let mut height: usize = 0;
+        let mut restart_interval: usize = 0;
+        let mut adobe_transform: Option<u8> = None;
+
+        let mut offset: usize = 2;
+        loop {
+            while offset < bytes.len() && bytes[offset] != 0xFF {
+                offset += 1;
+            }
+            while offset < bytes.len() && bytes[offset] == 0xFF {
+                offset += 1;
+            }
+            let Some(marker) = bytes.get(offset).copied() else {
+                panic!("Unexpected end of JPEG file '{}'!", path);
+            };
+            offset += 1;
+
+            if marker == 0xD9 {
+                break;
+            }
+            if (0xD0..=0xD7).contains(&marker) || marker == 0x00 || marker == 0x01 {
+                continue;
+            }
+
+            let length = u16::from_be_bytes([bytes[offset], bytes[offset + 1]]) as usize;
+            let segment = &bytes[offset + 2..offset + length];
+            offset += length;
+
+            match marker {
+                // DQT
+                0xDB => {
+                    let mut i: usize = 0;
+                    while i < segment.len() {
+                        let precision = segment[i] >> 4;
+                        let table_id = (segment[i] & 0x0F) as usize;
+                        i += 1;
+                        for k in 0..64 {
+                            quant_tables[table_id][ZIGZAG[k]] = if precision == 0 {
+                                segment[i + k] as u16
+                            } else {
+                                u16::from_be_bytes([segment[i + k * 2], segment[i + k * 2 + 1]])
+                            };
+                        }
+                        i += if precision == 0 { 64 } else { 128 };
+                    }
+                }
+                // DHT
+                0xC4 => {
+                    let mut i: usize = 0;
+                    while i < segment.len() {
+                        let class = segment[i] >> 4;
+                        let table_id = (segment[i] & 0x0F) as usize;
+                        let counts: [u8; 16] = segment[i + 1..i + 17].try_into().unwrap();
+                        let total: usize = counts.iter().map(|count| *count as usize).sum();
+                        let values = segment[i + 17..i + 17 + total].to_vec();
+                        i += 17 + total;
+                        if class == 0 {
+                            dc_tables[table_id] = HuffmanTable::new(&counts, values);
+                        } else {
+                            ac_tables[table_id] = HuffmanTable::new(&counts, values);
+                        }
+                    }
+                }
+                // Baseline and extended sequential DCT
+                0xC0 | 0xC1 => {
+                    if segment[0] != 8 {
+                        panic!("Only 8-bit JPEG sample precision is supported!");
+                    }
+                    height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+                    width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+                    let count = segment[5] as usize;
+                    for c in 0..count {
+                        let data = &segment[6 + c * 3..9 + c * 3];
+                        components.push(Component {
+                            id: data[0],
+                            h_sampling: (data[1] >> 4) as usize,
+                            v_sampling: (data[1] & 0x0F) as usize,
+                            quant_table_id: data[2] as usize,
+                            ..Default::default()
+                        });
+                    }
+
+                    let h_max = components.iter().map(|comp| comp.h_sampling).max().unwrap();
+                    let v_max = components.iter().map(|comp| comp.v_sampling).max().unwrap();
+                    for comp in components.iter_mut() {
+                        comp.blocks_per_line = width.div_ceil(8 * h_max) * comp.h_sampling;
+                        comp.blocks_per_column = height.div_ceil(8 * v_max) * comp.v_sampling;
+                        comp.samples = vec![0; comp.blocks_per_line * comp.blocks_per_column * 64];
+                    }
+                }
+                0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                    panic!("Only baseline JPEG files are supported, '{}' is not!", path);
+                }
+                // DRI
+                0xDD => {
+                    restart_interval = u16::from_be_bytes([segment[0], segment[1]]) as usize;
+                }
+                // APP14 (Adobe)
+                0xEE => {
+                    if segment.len() >= 12 && &segment[0..5] == b"Adobe" {
+                        adobe_transform = Some(segment[11]);
+                    }
+                }
+                // SOS
+                0xDA => {
+                    let count = segment[0] as usize;
+                    let mut scan_components: Vec<usize> = Vec::new();
+                    for c in 0..count {
+                        let id = segment[1 + c * 2];
+                        let tables = segment[2 + c * 2];
+                        let Some(index) = components.iter().position(|comp| comp.id == id) else {
+                            panic!("JPEG scan references unknown component {}!", id);
+                        };
+                        components[index].dc_table_id = (tables >> 4) as usize;
+                        components[index].ac_table_id = (tables & 0x0F) as usize;
+                        scan_components.push(index);
+                    }
+
+                    offset = Self::decode_scan(
+                        &bytes,
+                        offset,
+                        width,
+                        height,
+                        &mut components,
+                        &scan_components,
+                        &quant_tables,
+                        &dc_tables,
+                        &ac_tables,
+                        restart_interval,
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        if components.is_empty() {
+            panic!("JPEG file '{}' has no frame header!", path);
+        }
+
+        let h_max = components.iter().map(|comp| comp.h_sampling).max().unwrap();
+        let v_max = components.iter().map(|comp| comp.v_sampling).max().unwrap();
+        // Subsampled components are upsampled bilinearly between sample centers
+        let sample = |comp: &Component, x: usize, y: usize| -> f32 {
+            let comp_width = (width * comp.h_sampling).div_ceil(h_max);
+            let comp_height = (height * comp.v_sampling).div_ceil(v_max);
+            let fx = ((x as f32 + 0.5) * comp.h_sampling as f32 / h_max as f32 - 0.5)
+                .clamp(0.0, (comp_width - 1) as f32);
+            let fy = ((y as f32 + 0.5) * comp.v_sampling as f32 / v_max as f32 - 0.5)
+                .clamp(0.0, (comp_height - 1) as f32);
+            let x_0 = fx as usize;
+            let y_0 = fy as usize;
+            let x_1 = usize::min(x_0 + 1, comp_width - 1);
+            let y_1 = usize::min(y_0 + 1, comp_height - 1);
+            let tx = fx - x_0 as f32;
+            let ty = fy - y_0 as f32;
+
+            let line_width = comp.blocks_per_line * 8;
+            let at = |sx: usize, sy: usize| -> f32 { comp.samples[sy * line_width + sx] as f32 };
+            let top = at(x_0, y_0) * (1.0 - tx) + at(x_1, y_0) * tx;
+            let bottom = at(x_0, y_1) * (1.0 - tx) + at(x_1, y_1) * tx;
+            return top * (1.0 - ty) + bottom * ty;
+        };
+
+        let mut pixel_data: Vec<[u8; 3]> = Vec::new();
+        pixel_data.reserve_exact(width * height);
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let color: [u8; 3] = match components.len() {
+                    1 => {
+                        let luma = sample(&components[0], x, y).round() as u8;
+                        [luma, luma, luma]
+                    }
+                    3 if adobe_transform == Some(0) => [
+                        sample(&components[0], x, y).round() as u8,
+                        sample(&components[1], x, y).round() as u8,
+                        sample(&components[2], x, y).round() as u8,
+                    ],
+                    3 => {
+                        let luma = sample(&components[0], x, y);
+                        let cb = sample(&components[1], x, y) - 128.0;
+                        let cr = sample(&components[2], x, y) - 128.0;
+                        [
+                            (luma + 1.402 * cr).round().clamp(0.0, 255.0) as u8,
+                            (luma - 0.344136 * cb - 0.714136 * cr).round().clamp(0.0, 255.0) as u8,
+                            (luma + 1.772 * cb).round().clamp(0.0, 255.0) as u8,
+                        ]
+                    }
+                    _ => panic!(
+                        "Unsupported JPEG component count {} in '{}'!",
+                        components.len(),
+                        path
+                    ),
+                };
+                pixel_data.push(color);
+            }
+        }
+
+        return JPEG {
+            width: width as i32,
+            height: height as i32,
+            pixel_data,
+        };
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn decode_scan(
+        bytes: &[u8],
+        offset: usize,
+        width: usize,
+        height: usize,
+        components: &mut [Component],
+        scan_components: &[usize],
+        quant_tables: &[[u16; 64]; 4],
+        dc_tables: &[HuffmanTable; 4],
+        ac_tables: &[HuffmanTable; 4],
+        restart_interval: usize,
+    ) -> usize {
+        let h_max = components.iter().map(|comp| comp.h_sampling).max().unwrap();
+        let v_max = components.iter().map(|comp| comp.v_sampling).max().unwrap();
+        let mcus_per_line = width.div_ceil(8 * h_max);
+        let mcus_per_column = height.div_ceil(8 * v_max);
+
+        for &index in scan_components {
+            components[index].dc_prediction = 0;
+        }
+
+        let mut cosines = [[0.0f32; 8]; 8];
+        for x in 0..8 {
+            for u in 0..8 {
+                let scale = if u == 0 { f32::sqrt(0.125) } else { 0.5 };
+                cosines[x][u] = scale
+                    * f32::cos(((2 * x + 1) as f32 * u as f32 * std::f32::consts::PI) / 16.0);
+            }
+        }
+
+        let mut reader = BitReader::new(bytes, offset);
+        let mut block = [0i32; 64];
+
+        // A single component scan is not interleaved and covers only that component's blocks
+        if scan_components.len() == 1 {
+            let comp = &mut components[scan_components[0]];
+            let blocks_wide = (width * comp.h_sampling).div_ceil(8 * h_max);
+            let blocks_high = (height * comp.v_sampling).div_ceil(8 * v_max);
+            for block_id in 0..blocks_wide * blocks_high {
+                if restart_interval > 0 && block_id > 0 && block_id % restart_interval == 0 {
+                    reader.restart();
+                    comp.dc_prediction = 0;
+                }
+                Self::decode_block(
+                    &mut reader,
+                    comp,
+                    &mut block,
+                    &dc_tables[comp.dc_table_id],
+                    &ac_tables[comp.ac_table_id],
+                    &quant_tables[comp.quant_table_id],
+                );
+                Self::store_block(
+                    comp,
+                    &block,
+                    &cosines,
+                    block_id % blocks_wide,
+                    block_id / blocks_wide,
+                );
+            }
+        } else {
+            for mcu_id in 0..mcus_per_line * mcus_per_column {
+                if restart_interval > 0 && mcu_id > 0 && mcu_id % restart_interval == 0 {
+                    reader.restart();
+                    for &index in scan_components {
+                        components[index].dc_prediction = 0;
+                    }
+                }
+                let mcu_x = mcu_id % mcus_per_line;
+                let mcu_y = mcu_id / mcus_per_line;
+                for &index in scan_components {
+                    let comp = &mut components[index];
+                    for v in 0..comp.v_sampling {
+                        for h in 0..comp.h_sampling {
+                            Self::decode_block(
+                                &mut reader,
+                                comp,
+                                &mut block,
+                                &dc_tables[comp.dc_table_id],
+                                &ac_tables[comp.ac_table_id],
+                                &quant_tables[comp.quant_table_id],
+                            );
+                            Self::store_block(
+                                comp,
+                                &block,
+                                &cosines,
+                                mcu_x * comp.h_sampling + h,
+                                mcu_y * comp.v_sampling + v,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        return reader.position;
+    }
+
+    fn decode_block(
+        reader: &mut BitReader,
+        comp: &mut Component,
+        block: &mut [i32; 64],
+        dc_table: &HuffmanTable,
+        ac_table: &HuffmanTable,
+        quant_table: &[u16; 64],
+    ) {
+        block.fill(0);
+
+        let size = dc_table.decode(reader);
+        comp.dc_prediction += reader.receive_extend(size);
+        block[0] = comp.dc_prediction * quant_table[0] as i32;
+
+        let mut k: usize = 1;
+        while k < 64 {
+            let symbol = ac_table.decode(reader);
+            let run = (symbol >> 4) as usize;
+            let size = symbol & 0x0F;
+            if size == 0 {
+                if run == 15 {
+                    k += 16;
+                    continue;
+                }
+                break;
+            }
+            k += run;
+            if k > 63 {
+                break;
+            }
+            block[ZIGZAG[k]] = reader.receive_extend(size) * quant_table[ZIGZAG[k]] as i32;
+            k += 1;
+        }
+    }
+
+    fn store_block(
+        comp: &mut Component,
+        block: &[i32; 64],
+        cosines: &[[f32; 8]; 8],
+        block_x: usize,
+        block_y: usize,
+    ) {
+        if block_x >= comp.blocks_per_line || block_y >= comp.blocks_per_column {
+            return;
+        }
+
+        let pixels = Self::idct(block, cosines);
+        let line_width = comp.blocks_per_line * 8;
+        for y in 0..8 {
+            let row_start = (block_y * 8 + y) * line_width + block_x * 8;
+            comp.samples[row_start..row_start + 8].copy_from_slice(&pixels[y * 8..y * 8 + 8]);
+        }
+    }
+
+    /// Separable 8x8 inverse DCT, level shifted back into the 0 - 255 range
+    fn idct(block: &[i32; 64], cosines: &[[f32; 8]; 8]) -> [u8; 64] {
+        let mut rows = [0.0f32; 64];
+        for y in 0..8 {
+            for x in 0..8 {
+                let mut sum = 0.0;
+                for u in 0..8 {
+                    sum += cosines[x][u] * block[y * 8 + u] as f32;
+                }
+                rows[y * 8 + x] = sum;
+            }
+        }
+
+        let mut pixels = [0u8; 64];
+        for x in 0..8 {
+            for y in 0..8 {
+                let mut sum = 0.0;
+                for v in 0..8 {
+                    sum += cosines[y][v] * rows[v * 8 + x];
+                }
+                pixels[y * 8 + x] = (sum + 128.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+
+        return pixels;
+    }
+}
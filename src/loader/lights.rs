@@ -0,0 +1,135 @@
+use crate::light::{Light, LightKind};
+use crate::{Vec3f, log_info, log_warning};
+
+/// Lights listed next to a scene, `light` starts a new one and the lines after it set its values:
+///
+/// ```text
+/// light spot
+/// position 0 4 0
+/// direction 0 -1 0
+/// angles 20 30
+/// color 1 0.9 0.8
+/// intensity 50
+/// ```
+///
+/// Types are `point`, `spot`, `directional` and `area`, the last one takes `corner`, `edge_u`
+/// and `edge_v`. Spot angles are in degrees. Values left out default to a white light of
+/// intensity 1 at the origin, pointing down, with 20 and 30 degree spot angles and a unit square
+/// area centered on the origin.
+#[derive(Default)]
+pub struct Lights {
+    pub lights: Vec<Light>,
+}
+
+impl Lights {
+    pub fn load(path: &str) -> Self {
+        let mut file = Lights::default();
+
+        let start_time = std::time::Instant::now();
+
+        let buffer = std::fs::read_to_string(path).unwrap();
+        let lines = buffer
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("#"));
+
+        let down = Vec3f::new(0.0, -1.0, 0.0);
+        for line in lines {
+            let mut split = line.split_whitespace();
+            let Some(prefix) = split.next() else {
+                continue;
+            };
+
+            if prefix == "light" {
+                let kind = match split.next() {
+                    Some("point") => LightKind::Point {
+                        position: Vec3f::from(0.0),
+                    },
+                    Some("spot") => LightKind::Spot {
+                        position: Vec3f::from(0.0),
+                        direction: down,
+                        inner_angle: 20.0f32.to_radians(),
+                        outer_angle: 30.0f32.to_radians(),
+                    },
+                    Some("directional") => LightKind::Directional { direction: down },
+                    Some("area") => LightKind::Quad {
+                        corner: Vec3f::new(-0.5, 0.0, -0.5),
+                        edge_u: Vec3f::new(0.0, 0.0, 1.0),
+                        edge_v: Vec3f::new(1.0, 0.0, 0.0),
+                    },
+                    kind => panic!("Unknown light type '{}'!", kind.unwrap_or("")),
+                };
+                file.lights.push(Light {
+                    kind,
+                    color: Vec3f::from(1.0),
+                    intensity: 1.0,
+                });
+                continue;
+            }
+
+            let Some(light) = file.lights.last_mut() else {
+                panic!("Light value '{}' comes before the first light!", prefix);
+            };
+            let values: Vec<f32> = split.map(|value| value.parse().unwrap()).collect();
+            let count = match prefix {
+                "intensity" => 1,
+                "angles" => 2,
+                "color" | "position" | "direction" | "corner" | "edge_u" | "edge_v" => 3,
+                // Unknown values are reported by the match below
+                _ => values.len(),
+            };
+            if values.len() != count {
+                log_warning!(
+                    "Ignoring light value '{}', it needs {} numbers but has {}",
+                    prefix,
+                    count,
+                    values.len()
+                );
+                continue;
+            }
+            let vector = || Vec3f::new(values[0], values[1], values[2]);
+
+            match (prefix, &mut light.kind) {
+                ("color", _) => light.color = vector(),
+                ("intensity", _) => light.intensity = values[0],
+                ("position", LightKind::Point { position } | LightKind::Spot { position, .. }) => {
+                    *position = vector();
+                }
+                (
+                    "direction",
+                    LightKind::Spot { direction, .. } | LightKind::Directional { direction },
+                ) => {
+                    *direction = vector();
+                }
+                (
+                    "angles",
+                    LightKind::Spot {
+                        inner_angle,
+                        outer_angle,
+                        ..
+                    },
+                ) => {
+                    *inner_angle = values[0].to_radians();
+                    *outer_angle = values[1].to_radians();
+                }
+                ("corner", LightKind::Quad { corner, .. }) => *corner = vector(),
+                ("edge_u", LightKind::Quad { edge_u, .. }) => *edge_u = vector(),
+                ("edge_v", LightKind::Quad { edge_v, .. }) => *edge_v = vector(),
+                _ => {
+                    log_warning!(
+                        "Ignoring light value '{}' on a {} light",
+                        prefix,
+                        light.kind.name()
+                    );
+                }
+            }
+        }
+
+        log_info!(
+            "'{}' took {} ms to load\n",
+            path,
+            start_time.elapsed().as_millis()
+        );
+
+        return file;
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{Vec3f, log_info, scene::Material, texture::Texture};
+use crate::bsdf::{ComplexIor, Metal};
+use crate::texture::{ColorSpace, FilterMode, Sampler, Texture, WrapMode};
+use crate::{Vec3f, log_info, log_warning, scene::Material};
 use std::str::FromStr;
 
 #[derive(Default)]
@@ -164,21 +166,151 @@ impl OBJ {
                         "Pm" => {
                             material.metallic = attribute.next().unwrap().parse().unwrap();
                         }
+                        "Pmp" => {
+                            let name = attribute.next().unwrap();
+                            match Metal::from_name(name) {
+                                Some(metal) => material.conductor = Some(metal.complex_ior()),
+                                None => {
+                                    log_warning!(
+                                        "Unknown metal preset '{}', using base color",
+                                        name
+                                    );
+                                }
+                            }
+                        }
+                        "Pme" => {
+                            let conductor =
+                                material.conductor.get_or_insert_with(ComplexIor::default);
+                            attribute.into_iter().enumerate().for_each(|(i, val)| {
+                                conductor.eta.data[i] = val.parse().unwrap();
+                            });
+                        }
+                        "Pmk" => {
+                            let conductor =
+                                material.conductor.get_or_insert_with(ComplexIor::default);
+                            attribute.into_iter().enumerate().for_each(|(i, val)| {
+                                conductor.k.data[i] = val.parse().unwrap();
+                            });
+                        }
+                        "aniso" => {
+                            material.anisotropy = attribute.next().unwrap().parse().unwrap();
+                        }
+                        "anisor" => {
+                            material.anisotropy_rotation =
+                                attribute.next().unwrap().parse().unwrap();
+                        }
+                        "Pss" => {
+                            material.subsurface = attribute.next().unwrap().parse().unwrap();
+                        }
+                        "Pssc" => {
+                            attribute.into_iter().enumerate().for_each(|(i, val)| {
+                                material.subsurface_color.data[i] = val.parse().unwrap();
+                            });
+                        }
+                        "Pssr" => {
+                            attribute.into_iter().enumerate().for_each(|(i, val)| {
+                                material.subsurface_radius.data[i] = val.parse().unwrap();
+                            });
+                        }
+                        "Ptf" => {
+                            material.thin_film_thickness =
+                                attribute.next().unwrap().parse().unwrap();
+                        }
+                        "Ptfi" => {
+                            material.thin_film_ior = attribute.next().unwrap().parse().unwrap();
+                        }
+                        "Pc" => {
+                            material.clearcoat = attribute.next().unwrap().parse().unwrap();
+                        }
+                        "Pcr" => {
+                            material.clearcoat_roughness =
+                                attribute.next().unwrap().parse().unwrap();
+                        }
+                        "Pci" => {
+                            material.clearcoat_ior = attribute.next().unwrap().parse().unwrap();
+                        }
+                        "Ps" => {
+                            // Either a single strength or a full strength sheen color
+                            let values: Vec<f32> =
+                                attribute.map(|val| val.parse().unwrap()).collect();
+                            if values.len() >= 3 {
+                                material.sheen = 1.0;
+                                material.sheen_tint = Vec3f::new(values[0], values[1], values[2]);
+                            } else {
+                                material.sheen = values[0];
+                            }
+                        }
+                        "d" => {
+                            material.opacity = attribute.next().unwrap().parse().unwrap();
+                        }
+                        "Tr" => {
+                            let transparency: f32 = attribute.next().unwrap().parse().unwrap();
+                            material.opacity = 1.0 - transparency;
+                        }
                         "Tf" => {
                             material.transmission = attribute.next().unwrap().parse().unwrap();
                         }
+                        "Ta" => {
+                            attribute.into_iter().enumerate().for_each(|(i, val)| {
+                                material.absorption_color.data[i] = val.parse().unwrap();
+                            });
+                        }
+                        "Td" => {
+                            material.absorption_density = attribute.next().unwrap().parse().unwrap();
+                        }
                         "map_Kd" => {
-                            let texture = Texture::load(attribute.next().unwrap());
+                            let (texture_path, sampler, _) = Self::parse_texture_options(attribute);
+                            let texture = Texture::load(&texture_path, ColorSpace::Srgb);
                             if texture.is_some() {
                                 obj.textures.push(texture.unwrap());
                                 material.base_color_tex_id = (obj.textures.len() - 1) as i32;
+                                material.base_color_sampler = sampler;
                             }
                         }
                         "map_Ke" => {
-                            let texture = Texture::load(attribute.next().unwrap());
+                            let (texture_path, sampler, _) = Self::parse_texture_options(attribute);
+                            let texture = Texture::load(&texture_path, ColorSpace::Srgb);
                             if texture.is_some() {
                                 obj.textures.push(texture.unwrap());
                                 material.emission_tex_id = (obj.textures.len() - 1) as i32;
+                                material.emission_sampler = sampler;
+                            }
+                        }
+                        "map_d" => {
+                            let (texture_path, sampler, _) = Self::parse_texture_options(attribute);
+                            let texture = Texture::load(&texture_path, ColorSpace::Linear);
+                            if texture.is_some() {
+                                obj.textures.push(texture.unwrap());
+                                material.opacity_tex_id = (obj.textures.len() - 1) as i32;
+                                material.opacity_sampler = sampler;
+                            }
+                        }
+                        "norm" | "map_Kn" => {
+                            let (texture_path, sampler, _) = Self::parse_texture_options(attribute);
+                            let texture = Texture::load(&texture_path, ColorSpace::Linear);
+                            if texture.is_some() {
+                                obj.textures.push(texture.unwrap());
+                                material.normal_tex_id = (obj.textures.len() - 1) as i32;
+                                material.normal_sampler = sampler;
+                            }
+                        }
+                        "map_Bump" | "map_bump" | "bump" => {
+                            let (texture_path, sampler, bump_multiplier) =
+                                Self::parse_texture_options(attribute);
+                            let Some(texture) = Texture::load(&texture_path, ColorSpace::Linear) else {
+                                continue;
+                            };
+                            // Many exporters write tangent space normal maps to the bump slot
+                            if texture.is_normal_map() {
+                                log_info!("Using bump map '{}' as a normal map", texture_path);
+                                obj.textures.push(texture);
+                                material.normal_tex_id = (obj.textures.len() - 1) as i32;
+                                material.normal_sampler = sampler;
+                            } else {
+                                obj.textures.push(texture);
+                                material.bump_tex_id = (obj.textures.len() - 1) as i32;
+                                material.bump_sampler = sampler;
+                                material.bump_strength = bump_multiplier;
                             }
                         }
                         _ => continue,
@@ -189,6 +321,88 @@ impl OBJ {
             }
         }
     }
+
+    /// map_Kd -clamp on texture.png
+    /// map_Kd -wrap mirror clamp -filter nearest texture.png
+    ///
+    /// map_Bump -bm 0.5 height.png
+    ///
+    /// `-wrap` (one mode or separate u and v modes) and `-filter` extend the MTL options.
+    /// Returns the `-bm` bump multiplier, other MTL options are skipped along with their arguments.
+    fn parse_texture_options(attribute: std::str::SplitWhitespace) -> (String, Sampler, f32) {
+        let mut sampler = Sampler::default();
+        let mut texture_path = String::new();
+        let mut bump_multiplier: f32 = 1.0;
+
+        let parse_wrap = |value: &str| -> Option<WrapMode> {
+            match value {
+                "repeat" => return Some(WrapMode::Repeat),
+                "clamp" => return Some(WrapMode::Clamp),
+                "mirror" => return Some(WrapMode::Mirror),
+                _ => return None,
+            }
+        };
+
+        let mut arguments = attribute.peekable();
+        while let Some(argument) = arguments.next() {
+            match argument {
+                "-clamp" => {
+                    let mode = match arguments.next() {
+                        Some("on") => WrapMode::Clamp,
+                        _ => WrapMode::Repeat,
+                    };
+                    sampler.wrap_u = mode;
+                    sampler.wrap_v = mode;
+                }
+                "-wrap" => {
+                    let Some(mode_u) = arguments.next().and_then(parse_wrap) else {
+                        log_warning!("Unknown texture wrap mode, using repeat");
+                        continue;
+                    };
+                    sampler.wrap_u = mode_u;
+                    sampler.wrap_v = mode_u;
+                    if let Some(mode_v) = arguments.peek().and_then(|value| parse_wrap(value)) {
+                        sampler.wrap_v = mode_v;
+                        arguments.next();
+                    }
+                }
+                "-filter" => match arguments.next() {
+                    Some("nearest") => sampler.filter = FilterMode::Nearest,
+                    Some("bilinear") => sampler.filter = FilterMode::Bilinear,
+                    Some("trilinear") => sampler.filter = FilterMode::Trilinear,
+                    _ => {
+                        log_warning!("Unknown texture filter mode, using trilinear");
+                    }
+                },
+                "-bm" => match arguments.next().and_then(|value| value.parse::<f32>().ok()) {
+                    Some(value) => bump_multiplier = value,
+                    None => {
+                        log_warning!("Invalid bump multiplier, using 1.0");
+                    }
+                },
+                "-blendu" | "-blendv" | "-cc" | "-texres" | "-imfchan" | "-boost" => {
+                    arguments.next();
+                }
+                "-mm" => {
+                    arguments.next();
+                    arguments.next();
+                }
+                "-o" | "-s" | "-t" => {
+                    for _ in 0..3 {
+                        if arguments
+                            .peek()
+                            .is_some_and(|value| value.parse::<f32>().is_ok())
+                        {
+                            arguments.next();
+                        }
+                    }
+                }
+                _ => texture_path = argument.to_string(),
+            }
+        }
+
+        return (texture_path, sampler, bump_multiplier);
+    }
 }
 
 #[derive(Default)]
@@ -0,0 +1,275 @@
+use crate::loader::inflate;
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Adam7 pass origins and steps as (x_start, y_start, x_step, y_step)
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+pub struct PNG {
+    pub width: i32,
+    pub height: i32,
+    pub pixel_data: Vec<[u8; 4]>,
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        return match self.color_type {
+            0 => 1,
+            2 => 3,
+            3 => 1,
+            4 => 2,
+            6 => 4,
+            _ => panic!("Unsupported PNG color type {}!", self.color_type),
+        };
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        return self.channels() * self.bit_depth as usize;
+    }
+
+    /// Byte distance to the corresponding byte of the previous pixel, used by the filters
+    fn filter_stride(&self) -> usize {
+        return usize::max(1, self.bits_per_pixel() / 8);
+    }
+
+    fn row_bytes(&self, width: usize) -> usize {
+        return (width * self.bits_per_pixel()).div_ceil(8);
+    }
+}
+
+impl PNG {
+    pub fn load(path: &str) -> Self {
+        let bytes = std::fs::read(path).unwrap();
+        if bytes.len() < SIGNATURE.len() || bytes[0..8] != SIGNATURE {
+            panic!("File identifier of file '{}' is incorrect!", path);
+        }
+
+        let mut header: Option<Header> = None;
+        let mut palette: Vec<[u8; 4]> = Vec::new();
+        let mut transparent_key: Option<[u16; 3]> = None;
+        let mut compressed: Vec<u8> = Vec::new();
+
+        let mut offset: usize = 8;
+        while offset + 8 <= bytes.len() {
+            let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+            let chunk_type = &bytes[offset + 4..offset + 8];
+            let data_start = offset + 8;
+            let data_end = data_start + length;
+            if data_end + 4 > bytes.len() {
+                panic!("PNG chunk in file '{}' is truncated!", path);
+            }
+            let data = &bytes[data_start..data_end];
+
+            match chunk_type {
+                b"IHDR" => {
+                    let compression_method = data[10];
+                    let filter_method = data[11];
+                    if compression_method != 0 || filter_method != 0 {
+                        panic!("Compression and filter method must be zero!");
+                    }
+                    header = Some(Header {
+                        width: u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize,
+                        height: u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize,
+                        bit_depth: data[8],
+                        color_type: data[9],
+                        interlaced: data[12] == 1,
+                    });
+                }
+                b"PLTE" => {
+                    palette = data
+                        .chunks_exact(3)
+                        .map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                        .collect();
+                }
+                b"tRNS" => match header.as_ref().map(|header| header.color_type) {
+                    Some(0) => {
+                        let gray = u16::from_be_bytes([data[0], data[1]]);
+                        transparent_key = Some([gray; 3]);
+                    }
+                    Some(2) => {
+                        transparent_key = Some([
+                            u16::from_be_bytes([data[0], data[1]]),
+                            u16::from_be_bytes([data[2], data[3]]),
+                            u16::from_be_bytes([data[4], data[5]]),
+                        ]);
+                    }
+                    Some(3) => {
+                        for (i, alpha) in data.iter().enumerate() {
+                            if let Some(entry) = palette.get_mut(i) {
+                                entry[3] = *alpha;
+                            }
+                        }
+                    }
+                    _ => (),
+                },
+                b"IDAT" => compressed.extend_from_slice(data),
+                b"IEND" => break,
+                _ => (),
+            }
+
+            offset = data_end + 4;
+        }
+
+        let Some(header) = header else {
+            panic!("PNG file '{}' has no IHDR chunk!", path);
+        };
+
+        let filtered = inflate::zlib_decompress(&compressed);
+
+        let mut samples: Vec<[u16; 4]> = vec![[0; 4]; header.width * header.height];
+        if header.interlaced {
+            let mut pass_offset: usize = 0;
+            for (x_start, y_start, x_step, y_step) in ADAM7_PASSES {
+                let pass_width = (header.width + x_step - 1 - x_start) / x_step;
+                let pass_height = (header.height + y_step - 1 - y_start) / y_step;
+                if header.width <= x_start || header.height <= y_start {
+                    continue;
+                }
+                let pass_size = pass_height * (header.row_bytes(pass_width) + 1);
+                let pass = Self::unfilter(
+                    &header,
+                    &filtered[pass_offset..pass_offset + pass_size],
+                    pass_width,
+                    pass_height,
+                );
+                pass_offset += pass_size;
+
+                for y in 0..pass_height {
+                    for x in 0..pass_width {
+                        samples[(y_start + y * y_step) * header.width + x_start + x * x_step] =
+                            Self::read_sample(&header, &pass, pass_width, x, y);
+                    }
+                }
+            }
+        } else {
+            let image = Self::unfilter(&header, &filtered, header.width, header.height);
+            for y in 0..header.height {
+                for x in 0..header.width {
+                    samples[y * header.width + x] =
+                        Self::read_sample(&header, &image, header.width, x, y);
+                }
+            }
+        }
+
+        let max_value = (1u32 << header.bit_depth) - 1;
+        let to_u8 = |value: u16| -> u8 { ((value as u32 * 255 + max_value / 2) / max_value) as u8 };
+        let mut pixel_data: Vec<[u8; 4]> = Vec::new();
+        pixel_data.reserve_exact(samples.len());
+        for y in (0..header.height).rev() {
+            for x in 0..header.width {
+                let sample = samples[y * header.width + x];
+                let pixel: [u8; 4] = match header.color_type {
+                    3 => *palette.get(sample[0] as usize).unwrap_or(&[0, 0, 0, 255]),
+                    _ => {
+                        let mut alpha = to_u8(sample[3]);
+                        if transparent_key.is_some_and(|key| key == [sample[0], sample[1], sample[2]])
+                        {
+                            alpha = 0;
+                        }
+                        [to_u8(sample[0]), to_u8(sample[1]), to_u8(sample[2]), alpha]
+                    }
+                };
+                pixel_data.push(pixel);
+            }
+        }
+
+        return PNG {
+            width: header.width as i32,
+            height: header.height as i32,
+            pixel_data,
+        };
+    }
+
+    fn unfilter(header: &Header, filtered: &[u8], width: usize, height: usize) -> Vec<u8> {
+        let row_bytes = header.row_bytes(width);
+        let stride = header.filter_stride();
+        if filtered.len() < height * (row_bytes + 1) {
+            panic!("PNG image data is truncated!");
+        }
+
+        let mut image: Vec<u8> = vec![0; height * row_bytes];
+        for y in 0..height {
+            let filter_type = filtered[y * (row_bytes + 1)];
+            let src = &filtered[y * (row_bytes + 1) + 1..(y + 1) * (row_bytes + 1)];
+            for i in 0..row_bytes {
+                let a = if i >= stride { image[y * row_bytes + i - stride] } else { 0 };
+                let b = if y > 0 { image[(y - 1) * row_bytes + i] } else { 0 };
+                let c = if y > 0 && i >= stride {
+                    image[(y - 1) * row_bytes + i - stride]
+                } else {
+                    0
+                };
+                let predictor = match filter_type {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    4 => Self::paeth(a, b, c),
+                    _ => panic!("Invalid PNG filter type {}!", filter_type),
+                };
+                image[y * row_bytes + i] = src[i].wrapping_add(predictor);
+            }
+        }
+
+        return image;
+    }
+
+    fn paeth(a: u8, b: u8, c: u8) -> u8 {
+        let p = a as i16 + b as i16 - c as i16;
+        let pa = (p - a as i16).abs();
+        let pb = (p - b as i16).abs();
+        let pc = (p - c as i16).abs();
+        if pa <= pb && pa <= pc {
+            return a;
+        } else if pb <= pc {
+            return b;
+        } else {
+            return c;
+        }
+    }
+
+    /// Reads the pixel at (x, y) as RGBA samples at the image bit depth.
+    /// Palette images return the palette index in the first channel.
+    fn read_sample(header: &Header, image: &[u8], width: usize, x: usize, y: usize) -> [u16; 4] {
+        let row = &image[y * header.row_bytes(width)..(y + 1) * header.row_bytes(width)];
+        let channels = header.channels();
+        let mut values = [0u16; 4];
+        for channel in 0..channels {
+            let bit_offset = (x * channels + channel) * header.bit_depth as usize;
+            values[channel] = match header.bit_depth {
+                16 => u16::from_be_bytes([row[bit_offset / 8], row[bit_offset / 8 + 1]]),
+                8 => row[bit_offset / 8] as u16,
+                1 | 2 | 4 => {
+                    let shift = 8 - header.bit_depth as usize - (bit_offset % 8);
+                    ((row[bit_offset / 8] >> shift) & ((1u8 << header.bit_depth) - 1)) as u16
+                }
+                _ => panic!("Unsupported PNG bit depth {}!", header.bit_depth),
+            };
+        }
+
+        let opaque = ((1u32 << header.bit_depth) - 1) as u16;
+        return match header.color_type {
+            0 => [values[0], values[0], values[0], opaque],
+            2 => [values[0], values[1], values[2], opaque],
+            3 => [values[0], 0, 0, opaque],
+            4 => [values[0], values[0], values[0], values[1]],
+            _ => values,
+        };
+    }
+}
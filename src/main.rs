@@ -1,22 +1,29 @@
 use crate::vector::Vec3f;
 
 mod app;
+mod bsdf;
 mod bvh;
+mod color;
+mod environment;
 mod image;
+mod light;
 mod loader;
 mod log;
 mod ray;
 mod renderer;
 mod scene;
+mod sky;
 mod texture;
 mod vector;
 
 const WIDTH: usize = 800;
 const HEIGHT: usize = 600;
 const SAMPLE_COUNT: usize = 50;
-const MAX_BOUNCES: usize = 3;
+const MAX_BOUNCES: usize = 16;
+const RUSSIAN_ROULETTE_DEPTH: usize = 3;
 const DEBUG_BVH: bool = false;
 const OBJ_PATH: &str = "C:/Users/marce/Downloads/rust_ray_tracing-main/res/170320.obj";
+const ENVIRONMENT_PATH: Option<&str> = None;
 
 fn main() {
     log_info!("System logical cores: {}\n", rayon::current_num_threads());
@@ -26,8 +33,10 @@ fn main() {
     log_info!("- Height:       {}", HEIGHT);
     log_info!("- Sample count: {}", SAMPLE_COUNT);
     log_info!("- Max bounces:  {}", MAX_BOUNCES);
+    log_info!("- Roulette at:  {}", RUSSIAN_ROULETTE_DEPTH);
     log_info!("- BVH debug:    {}", DEBUG_BVH);
     log_info!("- Input file:   {}", OBJ_PATH);
+    log_info!("- Environment:  {}", ENVIRONMENT_PATH.unwrap_or("none"));
 
     log_info!("\nStarting application renderer...");
     log_info!("Controls:");
@@ -43,8 +52,10 @@ fn main() {
         HEIGHT,
         SAMPLE_COUNT,
         MAX_BOUNCES,
+        RUSSIAN_ROULETTE_DEPTH,
         DEBUG_BVH,
         OBJ_PATH.to_string(),
+        ENVIRONMENT_PATH.map(|path| path.to_string()),
     );
 
     app.run();
@@ -1,19 +1,51 @@
 use crate::Vec3f;
+use crate::bsdf::Bsdf;
 use crate::bvh::Node;
-use crate::scene::{Scene, Triangle};
+use crate::renderer::{Parameters, RenderMode};
+use crate::scene::{Material, Scene, Triangle};
 use crate::vector::Vec3Swizzles;
 
-const RAY_HIT_OFFSET: f32 = 0.0001;
+/// Rays leaving a surface ignore hits closer than this, so they do not find the same surface again
+const SURFACE_EPSILON: f32 = 0.0001;
+/// Slack around node bounds, keeps flat nodes from being missed
+const BOUNDS_EPSILON: f32 = 0.0001;
+/// Hits with less opacity than this are always passed through
+const ALPHA_CUTOFF: f32 = 0.05;
+/// Subsurface walks still inside after this many scattering events are dropped
+const MAX_WALK_STEPS: usize = 256;
 
 #[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Vec3f,
     pub direction: Vec3f,
+    /// Width of the ray cone at the origin, used to pick texture mip levels
+    pub cone_width: f32,
+    /// Angle in radians the ray cone widens by per unit of distance
+    pub cone_spread: f32,
+    /// Only hits with a distance between these two count
+    pub t_min: f32,
+    pub t_max: f32,
 }
 
 impl Ray {
     pub fn new(origin: Vec3f, direction: Vec3f) -> Self {
-        return Self { origin, direction };
+        return Self::with_range(origin, direction, 0.0, f32::MAX);
+    }
+
+    pub fn with_range(origin: Vec3f, direction: Vec3f, t_min: f32, t_max: f32) -> Self {
+        return Self {
+            origin,
+            direction,
+            cone_width: 0.0,
+            cone_spread: 0.0,
+            t_min,
+            t_max,
+        };
+    }
+
+    /// Ray leaving a surface at `point`, starting far enough away to not hit it again
+    fn from_surface(point: Vec3f, direction: Vec3f) -> Self {
+        return Self::with_range(point, direction, SURFACE_EPSILON, f32::MAX);
     }
 
     fn intersect_tri(ray: &Self, tri: &Triangle) -> HitInfo {
@@ -44,6 +76,12 @@ impl Ray {
         let n_1: Vec3f = tri.vertices[1].normal.into();
         let n_2: Vec3f = tri.vertices[2].normal.into();
         let mut normal: Vec3f = n_0 * (1.0 - u - v) + (n_1 * u) + (n_2 * v);
+        // Meshes without vertex normals leave them zero
+        if normal.length() > 1e-6 {
+            normal = normal.normalized();
+        } else {
+            normal = tri.geometric_normal();
+        }
         if !front_face {
             normal = normal.reversed();
         }
@@ -65,8 +103,30 @@ impl Ray {
         );
         let uv = (t_0 * (1.0 - u - v) + (t_1 * u) + (t_2 * v)).xy();
 
+        let tan_0 = Vec3f::new(
+            tri.vertices[0].tangent[0],
+            tri.vertices[0].tangent[1],
+            tri.vertices[0].tangent[2],
+        );
+        let tan_1 = Vec3f::new(
+            tri.vertices[1].tangent[0],
+            tri.vertices[1].tangent[1],
+            tri.vertices[1].tangent[2],
+        );
+        let tan_2 = Vec3f::new(
+            tri.vertices[2].tangent[0],
+            tri.vertices[2].tangent[1],
+            tri.vertices[2].tangent[2],
+        );
+        let tangent = tan_0 * (1.0 - u - v) + (tan_1 * u) + (tan_2 * v);
+        // Corners on either side of a UV mirror seam disagree on handedness, the closest ones win
+        let handedness = tri.vertices[0].tangent[3] * (1.0 - u - v)
+            + tri.vertices[1].tangent[3] * u
+            + tri.vertices[2].tangent[3] * v;
+
         return HitInfo {
-            has_hit: t > 0.0001
+            has_hit: t > ray.t_min
+                && t < ray.t_max
                 && !(det < 0.0 && det > -0.0)
                 && !(u < 0.0 || u > 1.0)
                 && !(v < 0.0 || u + v > 1.0),
@@ -74,7 +134,10 @@ impl Ray {
             normal: normal,
             distance: t,
             uv: uv,
+            tangent: tangent,
+            bitangent_sign: if handedness < 0.0 { -1.0 } else { 1.0 },
             material_id: tri.material_id,
+            tri_id: 0,
             front_face: front_face,
         };
     }
@@ -82,14 +145,20 @@ impl Ray {
     fn intersect_node(ray: &Self, node: &Node) -> bool {
         let t_min = (node.bounds_min - ray.origin) / ray.direction;
         let t_max = (node.bounds_max - ray.origin) / ray.direction;
-        let t_1 = Vec3f::min(t_min, t_max) - Vec3f::from(RAY_HIT_OFFSET);
-        let t_2 = Vec3f::max(t_min, t_max) + Vec3f::from(RAY_HIT_OFFSET);
+        let t_1 = Vec3f::min(t_min, t_max) - Vec3f::from(BOUNDS_EPSILON);
+        let t_2 = Vec3f::max(t_min, t_max) + Vec3f::from(BOUNDS_EPSILON);
         let t_near = f32::max(f32::max(t_1.x(), t_1.y()), t_1.z());
         let t_far = f32::min(f32::min(t_2.x(), t_2.y()), t_2.z());
-        return t_near < t_far && t_far > 0.0;
+        return t_near < t_far && t_far > ray.t_min && t_near < ray.t_max;
     }
 
-    fn traverse_bvh(ray: &Self, scene: &Scene, index: usize, hit_info: &mut HitInfo) {
+    fn traverse_bvh(
+        ray: &Self,
+        scene: &Scene,
+        index: usize,
+        hit_info: &mut HitInfo,
+        rng_state: &mut u32,
+    ) {
         let node = scene.bvh.nodes[index];
         if !Self::intersect_node(ray, &node) {
             return;
@@ -97,15 +166,86 @@ impl Ray {
 
         if node.num_tris > 0 {
             for i in 0..node.num_tris {
-                let temp_hit_info = Self::intersect_tri(ray, &scene.tris[node.first_tri_id + i]);
-                if temp_hit_info.has_hit && temp_hit_info.distance < hit_info.distance {
+                let tri_id = node.first_tri_id + i;
+                let temp_hit_info = Self::intersect_tri(ray, &scene.tris[tri_id]);
+                if temp_hit_info.has_hit
+                    && temp_hit_info.distance < hit_info.distance
+                    && Self::passes_alpha_test(ray, &temp_hit_info, tri_id, scene, rng_state)
+                {
                     *hit_info = temp_hit_info;
+                    hit_info.tri_id = tri_id;
                 }
             }
         } else {
-            Self::traverse_bvh(ray, scene, node.children_id, hit_info);
-            Self::traverse_bvh(ray, scene, node.children_id + 1, hit_info);
+            Self::traverse_bvh(ray, scene, node.children_id, hit_info, rng_state);
+            Self::traverse_bvh(ray, scene, node.children_id + 1, hit_info, rng_state);
+        }
+    }
+
+    /// Whether anything blocks `ray` before it travels `t_max`, stops at the first hit found
+    pub fn occluded(ray: &Self, t_max: f32, scene: &Scene, rng_state: &mut u32) -> bool {
+        let ray = Self {
+            t_max: f32::min(ray.t_max, t_max),
+            ..*ray
+        };
+        return Self::occluded_node(&ray, scene, 0, rng_state);
+    }
+
+    fn occluded_node(ray: &Self, scene: &Scene, index: usize, rng_state: &mut u32) -> bool {
+        let node = scene.bvh.nodes[index];
+        if !Self::intersect_node(ray, &node) {
+            return false;
         }
+
+        if node.num_tris > 0 {
+            for i in 0..node.num_tris {
+                let tri_id = node.first_tri_id + i;
+                let hit_info = Self::intersect_tri(ray, &scene.tris[tri_id]);
+                if hit_info.has_hit
+                    && Self::passes_alpha_test(ray, &hit_info, tri_id, scene, rng_state)
+                {
+                    return true;
+                }
+            }
+            return false;
+        }
+        return Self::occluded_node(ray, scene, node.children_id, rng_state)
+            || Self::occluded_node(ray, scene, node.children_id + 1, rng_state);
+    }
+
+    /// Cutout materials let rays through where they are transparent,
+    /// partially transparent hits are kept with a probability equal to their opacity
+    fn passes_alpha_test(
+        ray: &Self,
+        hit_info: &HitInfo,
+        tri_id: usize,
+        scene: &Scene,
+        rng_state: &mut u32,
+    ) -> bool {
+        let material = &scene.materials[hit_info.material_id];
+        let mut alpha = material.opacity;
+        if material.opacity_tex_id != -1 {
+            let texture = &scene.textures[material.opacity_tex_id as usize];
+            let footprint = Self::texture_footprint(
+                &scene.tris[tri_id],
+                ray.cone_width + ray.cone_spread * hit_info.distance,
+                f32::abs(Vec3f::dot(hit_info.normal, ray.direction)),
+            );
+            let texel = texture.sample(hit_info.uv, &material.opacity_sampler, footprint);
+            // Grayscale masks carry opacity in their color, RGBA images in their alpha
+            alpha *= if texture.has_alpha {
+                texel[3]
+            } else {
+                texel[0]
+            };
+        }
+
+        if alpha >= 1.0 {
+            return true;
+        } else if alpha < ALPHA_CUTOFF {
+            return false;
+        }
+        return Vec3f::rand_f32(rng_state) < alpha;
     }
 
     fn debug_bvh(ray: &Self, scene: &Scene, index: usize, debug_color: &mut Vec3f) {
@@ -127,100 +267,635 @@ impl Ray {
         }
     }
 
-    fn schlick_fresnel(n_dot_v: f32, ior: f32) -> f32 {
-        let f_0 = f32::powi(ior - 1.0, 2) / f32::powi(ior + 1.0, 2);
-        return f_0 + (1.0 - f_0) * f32::powi(1.0 - n_dot_v, 5);
+    /// Width in texture coordinates covered by a ray cone of `cone_width` hitting `tri`
+    fn texture_footprint(tri: &Triangle, cone_width: f32, cos_theta: f32) -> f32 {
+        let p_0 = Vec3f::from(tri.vertices[0].position);
+        let p_1 = Vec3f::from(tri.vertices[1].position);
+        let p_2 = Vec3f::from(tri.vertices[2].position);
+        let world_area = Vec3f::cross(p_1 - p_0, p_2 - p_0).length();
+
+        let [u_0, v_0] = tri.vertices[0].tex_coord;
+        let [u_1, v_1] = tri.vertices[1].tex_coord;
+        let [u_2, v_2] = tri.vertices[2].tex_coord;
+        let uv_area = f32::abs((u_1 - u_0) * (v_2 - v_0) - (u_2 - u_0) * (v_1 - v_0));
+
+        if world_area <= 0.0 {
+            return 0.0;
+        }
+        // Grazing hits stretch the cone's footprint across the surface
+        return cone_width / f32::max(cos_theta, 0.01) * f32::sqrt(uv_area / world_area);
+    }
+
+    /// Applies the material's normal map and bump map to the interpolated normal
+    fn shading_normal(
+        hit_info: &HitInfo,
+        material: &Material,
+        scene: &Scene,
+        footprint: f32,
+    ) -> Vec3f {
+        if material.normal_tex_id == -1 && material.bump_tex_id == -1 {
+            return hit_info.normal;
+        }
+
+        // Maps are authored against the outward normal, back faces flip the result afterwards
+        let side = if hit_info.front_face { 1.0 } else { -1.0 };
+        let normal = hit_info.normal * side;
+        let tangent = hit_info.tangent - normal * Vec3f::dot(normal, hit_info.tangent);
+        let (tangent, bitangent) = if tangent.length() > 1e-6 {
+            let tangent = tangent.normalized();
+            (
+                tangent,
+                Vec3f::cross(normal, tangent) * hit_info.bitangent_sign,
+            )
+        } else {
+            Vec3f::orthonormal_basis(normal)
+        };
+
+        let mut perturbed = normal;
+        if material.normal_tex_id != -1 {
+            let texel = scene.textures[material.normal_tex_id as usize].color_at(
+                hit_info.uv,
+                &material.normal_sampler,
+                footprint,
+            ) * 2.0
+                - Vec3f::from(1.0);
+            perturbed =
+                tangent * texel.data[0] + bitangent * texel.data[1] + normal * texel.data[2];
+        }
+        if material.bump_tex_id != -1 {
+            let texture = &scene.textures[material.bump_tex_id as usize];
+            let height = |uv: [f32; 2]| -> f32 {
+                return texture.sample(uv, &material.bump_sampler, footprint)[0];
+            };
+            // Finite differences over the filtered footprint, but never finer than a texel
+            let base = &texture.mip_levels[0];
+            let step_u = f32::max(footprint, 1.0 / base.width as f32);
+            let step_v = f32::max(footprint, 1.0 / base.height as f32);
+            let center = height(hit_info.uv);
+            let slope_u = (height([hit_info.uv[0] + step_u, hit_info.uv[1]]) - center)
+                * (1.0 / base.width as f32 / step_u);
+            let slope_v = (height([hit_info.uv[0], hit_info.uv[1] + step_v]) - center)
+                * (1.0 / base.height as f32 / step_v);
+            perturbed -= (tangent * slope_u + bitangent * slope_v) * material.bump_strength;
+        }
+
+        let perturbed = perturbed.normalized() * side;
+        // A normal tilted past the geometric normal would send rays through the surface
+        let facing = Vec3f::dot(perturbed, hit_info.normal);
+        if facing.is_nan() || facing <= 0.0 {
+            return hit_info.normal;
+        }
+        return perturbed;
+    }
+
+    /// Beer-Lambert attenuation over `distance` inside `material`
+    fn transmittance(material: &Material, distance: f32) -> Vec3f {
+        let mut transmittance = Vec3f::from(1.0);
+        if material.absorption_density <= 0.0 {
+            return transmittance;
+        }
+        for i in 0..3 {
+            let coefficient = -f32::ln(material.absorption_color.data[i].clamp(1e-6, 1.0))
+                * material.absorption_density;
+            transmittance.data[i] = f32::exp(-coefficient * distance);
+        }
+        return transmittance;
+    }
+
+    /// Follows light scattering inside `material` from `entry` until it reaches a surface again.
+    /// Returns where it left, the outward normal there and the throughput of the walk,
+    /// `None` when the walk escapes through an open mesh or takes too long.
+    fn random_walk(
+        entry: Vec3f,
+        direction: Vec3f,
+        material: &Material,
+        scene: &Scene,
+        rng_state: &mut u32,
+    ) -> Option<(Vec3f, Vec3f, Vec3f)> {
+        let mut extinction = Vec3f::from(0.0);
+        let mut albedo = Vec3f::from(0.0);
+        for i in 0..3 {
+            extinction.data[i] = 1.0 / f32::max(material.subsurface_radius.data[i], 1e-4);
+            // Inverts the multiple scattering albedo into a single scattering one, van de Hulst
+            let color = material.subsurface_color.data[i].clamp(0.0, 0.999);
+            albedo.data[i] = 1.0
+                - f32::powi(
+                    4.09712 + 4.20863 * color
+                        - f32::sqrt(9.59217 + 41.6808 * color + 17.7126 * color * color),
+                    2,
+                );
+        }
+
+        let mut throughput = Vec3f::from(1.0);
+        let mut walk = Self::from_surface(entry, direction);
+        for _ in 0..MAX_WALK_STEPS {
+            let mut hit_info = HitInfo::default();
+            Self::traverse_bvh(&walk, scene, 0, &mut hit_info, rng_state);
+            if !hit_info.has_hit {
+                return None;
+            }
+
+            // Distances follow one randomly picked channel, all three are weighted by their average pdf
+            let channel = usize::min((Vec3f::rand_f32(rng_state) * 3.0) as usize, 2);
+            let distance = -f32::ln(1.0 - Vec3f::rand_f32(rng_state)) / extinction.data[channel];
+            let travelled = f32::min(distance, hit_info.distance);
+            let mut transmittance = Vec3f::from(0.0);
+            for i in 0..3 {
+                transmittance.data[i] = f32::exp(-extinction.data[i] * travelled);
+            }
+
+            if distance >= hit_info.distance {
+                let pdf = (transmittance.x() + transmittance.y() + transmittance.z()) / 3.0;
+                throughput *= transmittance / pdf;
+                return Some((
+                    hit_info.point,
+                    hit_info.normal.reversed().normalized(),
+                    throughput,
+                ));
+            }
+
+            let density = extinction * transmittance;
+            let pdf = (density.x() + density.y() + density.z()) / 3.0;
+            throughput *= albedo * density / pdf;
+            walk = Self::new(
+                walk.origin + walk.direction * distance,
+                Vec3f::rand_in_unit_sphere(rng_state),
+            );
+        }
+        return None;
+    }
+
+    fn power_heuristic(pdf_a: f32, pdf_b: f32) -> f32 {
+        let a = pdf_a * pdf_a;
+        let b = pdf_b * pdf_b;
+        return a / (a + b);
+    }
+
+    /// Radiance arriving from `direction` when a ray leaves the scene.
+    /// The physical sky takes precedence over the environment map.
+    fn environment_radiance(direction: Vec3f, scene: &Scene, parameters: &Parameters) -> Vec3f {
+        if let Some(sky) = &parameters.sky {
+            return sky.radiance(direction);
+        }
+        match &scene.environment {
+            Some(environment) => {
+                return environment.radiance(direction, parameters.environment_rotation);
+            }
+            None => return Vec3f::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// Solid angle density of `sample_environment` picking `direction`
+    fn environment_pdf(direction: Vec3f, scene: &Scene, parameters: &Parameters) -> f32 {
+        if let Some(sky) = &parameters.sky {
+            return sky.sun_pdf(direction);
+        }
+        match &scene.environment {
+            Some(environment) => {
+                return environment.pdf(direction, parameters.environment_rotation);
+            }
+            None => return 0.0,
+        }
+    }
+
+    /// Samples the environment directly from a shading point, weighted against BSDF sampling.
+    /// Only the sun disc of the physical sky is sampled, the rest of it is found by BSDF sampling.
+    fn sample_environment(
+        ray: &Self,
+        hit_info: &HitInfo,
+        bsdf: &Bsdf,
+        scene: &Scene,
+        parameters: &Parameters,
+        rng_state: &mut u32,
+    ) -> Vec3f {
+        let (direction, light_pdf) = match (&parameters.sky, &scene.environment) {
+            (Some(sky), _) => sky.sample_sun(rng_state),
+            (None, Some(environment)) => {
+                environment.sample(parameters.environment_rotation, rng_state)
+            }
+            (None, None) => return Vec3f::from(0.0),
+        };
+        // Light can arrive from behind transmissive surfaces, the BSDF rejects it otherwise
+        let cos_theta = f32::abs(Vec3f::dot(hit_info.normal, direction));
+        if light_pdf <= 0.0 || cos_theta <= 0.0 {
+            return Vec3f::from(0.0);
+        }
+
+        let (bsdf_value, bsdf_pdf) = bsdf.evaluate(ray.direction.reversed(), direction);
+        if bsdf_pdf <= 0.0 {
+            return Vec3f::from(0.0);
+        }
+
+        let shadow_ray = Self::from_surface(hit_info.point, direction);
+        if Self::occluded(&shadow_ray, f32::MAX, scene, rng_state) {
+            return Vec3f::from(0.0);
+        }
+
+        let radiance = Self::environment_radiance(direction, scene, parameters)
+            * parameters.environment_intensity;
+        return radiance
+            * bsdf_value
+            * (cos_theta * Self::power_heuristic(light_pdf, bsdf_pdf) / light_pdf);
+    }
+
+    fn emitted_radiance(material: &Material, scene: &Scene, uv: [f32; 2], footprint: f32) -> Vec3f {
+        if material.emission_tex_id != -1 {
+            return scene.textures[material.emission_tex_id as usize].color_at(
+                uv,
+                &material.emission_sampler,
+                footprint,
+            );
+        }
+        return material.emission;
+    }
+
+    /// Solid angle density of `sample_emissive` at `origin` picking `direction` towards a point
+    /// `distance` away on `tri`
+    fn emissive_pdf(
+        origin: Vec3f,
+        tri: &Triangle,
+        tri_id: usize,
+        direction: Vec3f,
+        distance: f32,
+        scene: &Scene,
+    ) -> f32 {
+        let probability = scene.emissive.probability(origin, tri_id);
+        let cos_theta = f32::abs(Vec3f::dot(tri.geometric_normal(), direction));
+        let area = tri.area();
+        if probability <= 0.0 || cos_theta <= 0.0 || area <= 0.0 {
+            return 0.0;
+        }
+        return probability * distance * distance / (cos_theta * area);
+    }
+
+    /// Samples a point on an emissive triangle from a shading point, weighted against BSDF sampling.
+    /// Emissive triangles light both of their sides, the same as when a ray hits them.
+    fn sample_emissive(
+        ray: &Self,
+        hit_info: &HitInfo,
+        bsdf: &Bsdf,
+        scene: &Scene,
+        rng_state: &mut u32,
+    ) -> Vec3f {
+        if scene.emissive.is_empty() {
+            return Vec3f::from(0.0);
+        }
+
+        let (tri_id, _) = scene.emissive.sample(hit_info.point, rng_state);
+        let tri = &scene.tris[tri_id];
+        let (position, uv) =
+            tri.sample_point(Vec3f::rand_f32(rng_state), Vec3f::rand_f32(rng_state));
+        let to_light = position - hit_info.point;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return Vec3f::from(0.0);
+        }
+        let direction = to_light / distance;
+
+        let light_pdf = Self::emissive_pdf(hit_info.point, tri, tri_id, direction, distance, scene);
+        let cos_theta = f32::abs(Vec3f::dot(hit_info.normal, direction));
+        if light_pdf <= 0.0 || cos_theta <= 0.0 {
+            return Vec3f::from(0.0);
+        }
+
+        let (bsdf_value, bsdf_pdf) = bsdf.evaluate(ray.direction.reversed(), direction);
+        if bsdf_pdf <= 0.0 {
+            return Vec3f::from(0.0);
+        }
+
+        // Anything hit before the light itself blocks it
+        let shadow_ray = Self::from_surface(hit_info.point, direction);
+        if Self::occluded(&shadow_ray, distance - SURFACE_EPSILON, scene, rng_state) {
+            return Vec3f::from(0.0);
+        }
+
+        let material = &scene.materials[tri.material_id];
+        let radiance = Self::emitted_radiance(material, scene, uv, 0.0);
+        return radiance
+            * bsdf_value
+            * (cos_theta * Self::power_heuristic(light_pdf, bsdf_pdf) / light_pdf);
+    }
+
+    /// Samples every analytic light once from a shading point, weighted against BSDF sampling
+    fn sample_lights(
+        ray: &Self,
+        hit_info: &HitInfo,
+        bsdf: &Bsdf,
+        scene: &Scene,
+        rng_state: &mut u32,
+    ) -> Vec3f {
+        let mut radiance = Vec3f::from(0.0);
+        for light in &scene.lights {
+            let Some(sample) = light.sample(hit_info.point, rng_state) else {
+                continue;
+            };
+            let cos_theta = f32::abs(Vec3f::dot(hit_info.normal, sample.direction));
+            if cos_theta <= 0.0 {
+                continue;
+            }
+
+            let (bsdf_value, bsdf_pdf) = bsdf.evaluate(ray.direction.reversed(), sample.direction);
+            if bsdf_pdf <= 0.0 {
+                continue;
+            }
+
+            let shadow_ray = Self::from_surface(hit_info.point, sample.direction);
+            if Self::occluded(
+                &shadow_ray,
+                sample.distance - SURFACE_EPSILON,
+                scene,
+                rng_state,
+            ) {
+                continue;
+            }
+
+            // Only area lights can also be found by BSDF sampling
+            let weight = if sample.is_delta {
+                1.0
+            } else {
+                Self::power_heuristic(sample.pdf, bsdf_pdf)
+            };
+            radiance += sample.radiance * bsdf_value * (cos_theta * weight / sample.pdf);
+        }
+        return radiance;
+    }
+
+    /// Light from area lights a ray passes through before travelling `max_distance`,
+    /// attenuated by the medium the ray travels through on the way to each of them
+    fn hit_lights(
+        ray: &Self,
+        max_distance: f32,
+        specular_bounce: bool,
+        bsdf_pdf: f32,
+        medium: Option<&Material>,
+        scene: &Scene,
+    ) -> Vec3f {
+        let mut radiance = Vec3f::from(0.0);
+        for light in &scene.lights {
+            let Some((distance, mut emitted, light_pdf)) =
+                light.intersect(ray.origin, ray.direction, max_distance)
+            else {
+                continue;
+            };
+            if let Some(material) = medium {
+                emitted *= Self::transmittance(material, distance);
+            }
+            let weight = if specular_bounce {
+                1.0
+            } else {
+                Self::power_heuristic(bsdf_pdf, light_pdf)
+            };
+            radiance += emitted * weight;
+        }
+        return radiance;
     }
 
     pub fn trace(
         ray: &mut Self,
-        max_bounces: usize,
         scene: &Scene,
+        parameters: &Parameters,
         rng_state: &mut u32,
-        debug_bvh: bool,
     ) -> Vec3f {
+        // Throughput of the path so far, every light found along it is weighted by this
         let mut ray_color = Vec3f::new(1.0, 1.0, 1.0);
         let mut incoming_light = Vec3f::new(0.0, 0.0, 0.0);
-        let mut emitted_light = Vec3f::new(0.0, 0.0, 0.0);
 
-        let mut prev_hit_point: Vec3f = ray.origin;
-        let mut transmitted_distance: f32 = 0.0;
+        // Material whose volume the ray is travelling through
+        let mut medium: Option<usize> = None;
+
+        // Camera rays can only find the environment by hitting it
+        let mut specular_bounce = true;
+        let mut bsdf_pdf: f32 = 0.0;
+        // Shading point the ray left from, light selection depends on it
+        let mut shading_point = ray.origin;
 
         let mut curr_bounces: usize = 0;
-        while curr_bounces < max_bounces {
+        while curr_bounces < parameters.max_ray_depth {
             let mut hit_info = HitInfo::default();
 
-            if debug_bvh {
+            if parameters.debug_mode {
                 Self::debug_bvh(ray, scene, 0, &mut incoming_light);
                 return incoming_light;
             } else {
-                Self::traverse_bvh(ray, scene, 0, &mut hit_info);
+                Self::traverse_bvh(ray, scene, 0, &mut hit_info, rng_state);
             }
 
-            if hit_info.has_hit {
-                let hit_material = &scene.materials[hit_info.material_id];
-                let ior: f32;
-                if hit_info.front_face {
-                    ior = 1.0 / hit_material.ior;
-                    prev_hit_point = hit_info.point;
-                } else {
-                    ior = hit_material.ior;
-                    transmitted_distance = Vec3f::distance(hit_info.point, prev_hit_point);
-                }
+            // Area lights are not part of the BVH and do not block rays, they only add their light
+            let medium_material = medium.map(|medium_id| &scene.materials[medium_id]);
+            incoming_light += ray_color
+                * Self::hit_lights(
+                    ray,
+                    hit_info.distance,
+                    specular_bounce,
+                    bsdf_pdf,
+                    medium_material,
+                    scene,
+                );
 
-                let new_dir: Vec3f;
-                if Self::schlick_fresnel(Vec3f::dot(hit_info.normal, ray.direction.reversed()), ior)
-                    > Vec3f::rand_f32(rng_state)
-                {
-                    new_dir = Vec3f::reflect(ray.direction, hit_info.normal);
-                    ray_color *= hit_material.specular_tint;
-                } else {
-                    new_dir = Vec3f::refract(ray.direction, hit_info.normal, ior);
-                    if hit_material.base_color_tex_id != -1 {
-                        ray_color *= Vec3f::from(
-                            scene.textures[hit_material.base_color_tex_id as usize]
-                                .color_at(hit_info.uv),
-                        );
-                    } else {
-                        ray_color *= hit_material.base_color;
+            if !hit_info.has_hit {
+                let sky_color = Self::environment_radiance(ray.direction, scene, parameters);
+                let mut sky_strength = parameters.environment_intensity;
+                if !specular_bounce {
+                    let light_pdf = Self::environment_pdf(ray.direction, scene, parameters);
+                    if light_pdf > 0.0 {
+                        sky_strength *= Self::power_heuristic(bsdf_pdf, light_pdf);
                     }
                 }
+                incoming_light += ray_color * sky_color * sky_strength;
+                break;
+            }
 
-                if hit_material.emission_tex_id != -1 {
-                    emitted_light += Vec3f::from(
-                        scene.textures[hit_material.emission_tex_id as usize].color_at(hit_info.uv),
-                    );
-                } else {
-                    emitted_light += hit_material.emission;
-                }
-                let absorption = Vec3f::new(
-                    f32::exp(-0.1 * transmitted_distance),
-                    f32::exp(-3.0 * transmitted_distance),
-                    f32::exp(-5.0 * transmitted_distance),
+            // Everything found past this point was seen through the whole segment, rays escaping
+            // the scene have no end to theirs and reach the sky unattenuated
+            if let Some(material) = medium_material {
+                ray_color *= Self::transmittance(material, hit_info.distance);
+            }
+
+            let hit_material = &scene.materials[hit_info.material_id];
+            let cone_width = ray.cone_width + ray.cone_spread * hit_info.distance;
+            let footprint = Self::texture_footprint(
+                &scene.tris[hit_info.tri_id],
+                cone_width,
+                f32::abs(Vec3f::dot(hit_info.normal, ray.direction)),
+            );
+            hit_info.normal = Self::shading_normal(&hit_info, hit_material, scene, footprint);
+
+            let mut emission = Self::emitted_radiance(hit_material, scene, hit_info.uv, footprint);
+            if !specular_bounce && emission.luminance() > 0.0 {
+                let light_pdf = Self::emissive_pdf(
+                    shading_point,
+                    &scene.tris[hit_info.tri_id],
+                    hit_info.tri_id,
+                    ray.direction,
+                    hit_info.distance,
+                    scene,
                 );
-                ray_color *= absorption;
-                incoming_light += emitted_light * ray_color;
+                if light_pdf > 0.0 {
+                    emission *= Self::power_heuristic(bsdf_pdf, light_pdf);
+                }
+            }
+            incoming_light += ray_color * emission;
+
+            let base_color = if hit_material.base_color_tex_id != -1 {
+                scene.textures[hit_material.base_color_tex_id as usize].color_at(
+                    hit_info.uv,
+                    &hit_material.base_color_sampler,
+                    footprint,
+                )
+            } else {
+                hit_material.base_color
+            };
+
+            let bsdf = Bsdf::new(
+                hit_material,
+                base_color,
+                hit_info.normal,
+                hit_info.tangent,
+                hit_info.front_face,
+            );
+            incoming_light += ray_color
+                * Self::sample_environment(ray, &hit_info, &bsdf, scene, parameters, rng_state);
+            incoming_light +=
+                ray_color * Self::sample_emissive(ray, &hit_info, &bsdf, scene, rng_state);
+            incoming_light +=
+                ray_color * Self::sample_lights(ray, &hit_info, &bsdf, scene, rng_state);
+
+            let Some(sample) = bsdf.sample(ray.direction.reversed(), rng_state) else {
+                break;
+            };
+            let mut new_dir = sample.direction;
+            ray_color *= sample.weight;
+            bsdf_pdf = sample.pdf;
+            specular_bounce = false;
+
+            if sample.subsurface {
+                let Some((exit, exit_normal, walk_throughput)) =
+                    Self::random_walk(hit_info.point, new_dir, hit_material, scene, rng_state)
+                else {
+                    break;
+                };
+                ray_color *= walk_throughput;
+                // Light leaves diffusely, nothing sampled the environment at the exit point
+                new_dir = Vec3f::rand_cosine_hemisphere(rng_state, exit_normal);
+                hit_info.point = exit;
+                specular_bounce = true;
+            } else if Vec3f::dot(new_dir, hit_info.normal) < 0.0 {
+                // Refracted through the surface, into the material or back out of it
+                medium = if hit_info.front_face {
+                    Some(hit_info.material_id)
+                } else {
+                    None
+                };
+            }
 
-                *ray = Self::new(hit_info.point + new_dir * RAY_HIT_OFFSET, new_dir);
+            // Smooth reflection and refraction keep the cone's spread, rougher lobes widen it to
+            // the half angle of a cone covering the 1 / pdf steradians the sample stands for
+            let cone_spread = f32::max(
+                ray.cone_spread,
+                f32::sqrt(1.0 / (std::f32::consts::PI * sample.pdf)),
+            );
+            shading_point = hit_info.point;
+            *ray = Self::from_surface(hit_info.point, new_dir);
+            ray.cone_width = cone_width;
+            ray.cone_spread = cone_spread;
+
+            curr_bounces += 1;
+
+            // Dim paths end at random, the survivors carry the light of the ones that did not
+            if curr_bounces >= parameters.russian_roulette_depth {
+                let survival_probability = f32::min(
+                    f32::max(ray_color.x(), f32::max(ray_color.y(), ray_color.z())),
+                    0.95,
+                );
+                if Vec3f::rand_f32(rng_state) >= survival_probability {
+                    break;
+                }
+                ray_color /= survival_probability;
+            }
+        }
 
-                curr_bounces += 1;
-            } else {
-                let sky_color = Vec3f::new(1.0, 1.0, 1.0);
-                let sky_strength = Vec3f::from(1.0);
+        return incoming_light;
+    }
 
-                ray_color *= sky_color;
-                emitted_light += sky_strength;
-                incoming_light += emitted_light * ray_color;
+    /// Shows a property of the first hit instead of the light arriving along the ray,
+    /// rays that leave the scene are black
+    pub fn inspect(
+        ray: &Self,
+        scene: &Scene,
+        parameters: &Parameters,
+        rng_state: &mut u32,
+    ) -> Vec3f {
+        let mut hit_info = HitInfo::default();
+        Self::traverse_bvh(ray, scene, 0, &mut hit_info, rng_state);
+        if !hit_info.has_hit {
+            return Vec3f::from(0.0);
+        }
 
-                break;
+        let tri = &scene.tris[hit_info.tri_id];
+        let material = &scene.materials[hit_info.material_id];
+        let footprint = Self::texture_footprint(
+            tri,
+            ray.cone_width + ray.cone_spread * hit_info.distance,
+            f32::abs(Vec3f::dot(hit_info.normal, ray.direction)),
+        );
+        match parameters.render_mode {
+            RenderMode::PathTracing => return Vec3f::from(0.0),
+            RenderMode::AmbientOcclusion => {
+                let normal = Self::shading_normal(&hit_info, material, scene, footprint);
+                let direction = Vec3f::rand_cosine_hemisphere(rng_state, normal);
+                let occlusion_ray = Self::from_surface(hit_info.point, direction);
+                if Self::occluded(
+                    &occlusion_ray,
+                    parameters.ambient_occlusion_radius,
+                    scene,
+                    rng_state,
+                ) {
+                    return Vec3f::from(0.0);
+                }
+                return Vec3f::from(1.0);
             }
+            RenderMode::Albedo => {
+                if material.base_color_tex_id != -1 {
+                    return scene.textures[material.base_color_tex_id as usize].color_at(
+                        hit_info.uv,
+                        &material.base_color_sampler,
+                        footprint,
+                    );
+                }
+                return material.base_color;
+            }
+            RenderMode::ShadingNormals => {
+                let mut normal = Self::shading_normal(&hit_info, material, scene, footprint);
+                if !hit_info.front_face {
+                    normal = normal.reversed();
+                }
+                return normal * 0.5 + Vec3f::from(0.5);
+            }
+            RenderMode::GeometricNormals => {
+                return tri.geometric_normal() * 0.5 + Vec3f::from(0.5);
+            }
+            RenderMode::Uv => {
+                let [u, v] = hit_info.uv;
+                return Vec3f::new(u - f32::floor(u), v - f32::floor(v), 0.0);
+            }
+            RenderMode::Depth => {
+                let target_distance =
+                    Vec3f::distance(parameters.camera_pos, parameters.camera_target);
+                return Vec3f::from(hit_info.distance / f32::max(2.0 * target_distance, 1e-6));
+            }
+            RenderMode::MaterialId => return Self::id_color(hit_info.material_id),
+            RenderMode::TriangleId => return Self::id_color(hit_info.tri_id),
         }
+    }
 
-        if curr_bounces == 0 {
-            return incoming_light;
-        } else {
-            return incoming_light / curr_bounces as f32;
-        }
+    /// Color that stays the same for an id and differs between neighbouring ones
+    fn id_color(id: usize) -> Vec3f {
+        let mut state = (id as u32).wrapping_mul(2654435761).wrapping_add(1);
+        return Vec3f::new(
+            Vec3f::rand_f32(&mut state),
+            Vec3f::rand_f32(&mut state),
+            Vec3f::rand_f32(&mut state),
+        );
     }
 }
 
@@ -230,7 +905,10 @@ struct HitInfo {
     normal: Vec3f,
     distance: f32,
     uv: [f32; 2],
+    tangent: Vec3f,
+    bitangent_sign: f32,
     material_id: usize,
+    tri_id: usize,
     front_face: bool,
 }
 
@@ -242,7 +920,10 @@ impl Default for HitInfo {
             normal: Vec3f::default(),
             distance: f32::MAX,
             uv: [0.0; 2],
+            tangent: Vec3f::default(),
+            bitangent_sign: 1.0,
             material_id: 0,
+            tri_id: 0,
             front_face: false,
         };
     }
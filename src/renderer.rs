@@ -1,4 +1,6 @@
+use crate::color::{self, OutputTransform, ToneMapping};
 use crate::ray::Ray;
+use crate::sky::Sky;
 use crate::vector::Vec3f;
 use crate::{image::Image, log_info, scene::Scene};
 use rayon::prelude::*;
@@ -15,7 +17,7 @@ impl Renderer {
 
     pub fn render_to_image(&self, scene: &Scene, image: &mut Image) {
         let block_size = (image.width * image.height) / rayon::current_num_threads();
-        image.bytes = (0..image.width * image.height)
+        image.radiance = (0..image.width * image.height)
             .into_par_iter()
             .by_uniform_blocks(block_size)
             .map(|index: usize| {
@@ -44,14 +46,19 @@ impl Renderer {
                         )
                         .normalized(),
                     );
+                    // Each pixel spans 2 / height of the screen plane at unit distance
+                    ray.cone_spread = 2.0 / image.height as f32;
 
-                    final_color += Ray::trace(
-                        &mut ray,
-                        self.parameters.max_ray_depth,
-                        &scene,
-                        &mut rng_state,
-                        self.parameters.debug_mode,
-                    );
+                    // The BVH visualization happens while tracing
+                    if self.parameters.render_mode == RenderMode::PathTracing
+                        || self.parameters.debug_mode
+                    {
+                        final_color +=
+                            Ray::trace(&mut ray, &scene, &self.parameters, &mut rng_state);
+                    } else {
+                        final_color +=
+                            Ray::inspect(&ray, &scene, &self.parameters, &mut rng_state);
+                    }
 
                     // Only one sample is needed for BVH visualization
                     if self.parameters.debug_mode {
@@ -62,9 +69,22 @@ impl Renderer {
                 if !self.parameters.debug_mode {
                     final_color /= self.parameters.samples as f32;
                 }
-                final_color = Vec3f::linear_to_gamma(final_color);
+                return final_color;
+            })
+            .collect::<Vec<Vec3f>>();
 
-                return final_color.into();
+        image.bytes = self.develop(&image.radiance);
+    }
+
+    /// Turns linear radiance into display bytes, cheap enough to rerun whenever the look changes
+    pub fn develop(&self, radiance: &[Vec3f]) -> Vec<u8> {
+        let exposure = f32::exp2(self.parameters.exposure);
+        let white_balance = color::white_balance(self.parameters.white_balance);
+        return radiance
+            .par_iter()
+            .map(|linear| {
+                let color = self.parameters.tone_mapping.apply(*linear * exposure * white_balance);
+                return self.parameters.output_transform.apply(color).into();
             })
             .collect::<Vec<[u8; 3]>>()
             .into_flattened();
@@ -80,13 +100,78 @@ impl Default for Renderer {
     }
 }
 
+/// What a pixel shows, everything besides path tracing is for inspecting the scene
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum RenderMode {
+    #[default]
+    PathTracing,
+    /// Fraction of the hemisphere above a hit that is open within `ambient_occlusion_radius`
+    AmbientOcclusion,
+    /// Base color without any lighting
+    Albedo,
+    /// Normals after interpolation and normal maps, mapped from -1.0 - 1.0 to 0.0 - 1.0
+    ShadingNormals,
+    /// Triangle normals from the winding order, mapped like the shading normals
+    GeometricNormals,
+    /// Texture coordinates wrapped into 0.0 - 1.0 as red and green
+    Uv,
+    /// Hit distance, half of the distance to the camera target maps to 0.5
+    Depth,
+    /// A random color per material
+    MaterialId,
+    /// A random color per triangle
+    TriangleId,
+}
+
+impl RenderMode {
+    pub const ALL: [Self; 9] = [
+        Self::PathTracing,
+        Self::AmbientOcclusion,
+        Self::Albedo,
+        Self::ShadingNormals,
+        Self::GeometricNormals,
+        Self::Uv,
+        Self::Depth,
+        Self::MaterialId,
+        Self::TriangleId,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PathTracing => return "Path tracing",
+            Self::AmbientOcclusion => return "Ambient occlusion",
+            Self::Albedo => return "Albedo",
+            Self::ShadingNormals => return "Shading normals",
+            Self::GeometricNormals => return "Geometric normals",
+            Self::Uv => return "UV",
+            Self::Depth => return "Depth",
+            Self::MaterialId => return "Material ID",
+            Self::TriangleId => return "Triangle ID",
+        }
+    }
+}
+
 pub struct Parameters {
     pub samples: usize,
     pub max_ray_depth: usize,
+    /// Bounces after which paths are ended at random by russian roulette
+    pub russian_roulette_depth: usize,
     pub debug_mode: bool,
+    pub render_mode: RenderMode,
+    /// Distance within which geometry occludes in the ambient occlusion mode
+    pub ambient_occlusion_radius: f32,
     pub camera_pos: Vec3f,
     pub camera_target: Vec3f,
     pub camera_up: Vec3f,
+    pub environment_rotation: f32,
+    pub environment_intensity: f32,
+    pub sky: Option<Sky>,
+    /// Exposure adjustment in stops
+    pub exposure: f32,
+    /// Color temperature in Kelvin of the light that should appear white
+    pub white_balance: f32,
+    pub tone_mapping: ToneMapping,
+    pub output_transform: OutputTransform,
 }
 
 impl Clone for Parameters {
@@ -94,10 +179,20 @@ impl Clone for Parameters {
         Self {
             samples: self.samples,
             max_ray_depth: self.max_ray_depth,
+            russian_roulette_depth: self.russian_roulette_depth,
             debug_mode: self.debug_mode,
+            render_mode: self.render_mode,
+            ambient_occlusion_radius: self.ambient_occlusion_radius,
             camera_pos: self.camera_pos,
             camera_target: self.camera_target,
             camera_up: self.camera_up,
+            environment_rotation: self.environment_rotation,
+            environment_intensity: self.environment_intensity,
+            sky: self.sky,
+            exposure: self.exposure,
+            white_balance: self.white_balance,
+            tone_mapping: self.tone_mapping,
+            output_transform: self.output_transform,
         }
     }
 }
@@ -107,10 +202,20 @@ impl Default for Parameters {
         return Self {
             samples: 1,
             max_ray_depth: 6,
+            russian_roulette_depth: 3,
             debug_mode: false,
+            render_mode: RenderMode::default(),
+            ambient_occlusion_radius: 1.0,
             camera_pos: Vec3f::new(0.0, 0.0, 8.0),
             camera_target: Vec3f::new(0.0, 0.0, 0.0),
             camera_up: Vec3f::new(0.0, 1.0, 0.0),
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+            sky: None,
+            exposure: 0.0,
+            white_balance: color::NEUTRAL_TEMPERATURE,
+            tone_mapping: ToneMapping::default(),
+            output_transform: OutputTransform::default(),
         };
     }
 }
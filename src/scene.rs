@@ -1,7 +1,12 @@
+use crate::bsdf::ComplexIor;
 use crate::bvh::BVH;
+use crate::environment::Environment;
+use crate::light::{Light, LightTree};
+use crate::loader::lights::Lights;
 use crate::loader::obj::OBJ;
-use crate::texture::Texture;
+use crate::texture::{Sampler, Texture};
 use crate::{Vec3f, log_error};
+use std::collections::HashMap;
 
 #[derive(Clone, Default)]
 pub struct Scene {
@@ -9,6 +14,10 @@ pub struct Scene {
     pub materials: Vec<Material>,
     pub textures: Vec<Texture>,
     pub bvh: BVH,
+    pub environment: Option<Environment>,
+    /// Emissive triangles
+    pub emissive: LightTree,
+    pub lights: Vec<Light>,
 }
 
 impl Scene {
@@ -20,7 +29,15 @@ impl Scene {
 
         let format = path.split(".").last().unwrap();
         match format {
-            "obj" => Some(OBJ::load(path).into()),
+            "obj" => {
+                let mut scene: Scene = OBJ::load(path).into();
+                // Lights that are not geometry come from a file with the same name
+                let lights_path = format!("{}.lights", path.rsplit_once('.').unwrap().0);
+                if std::path::Path::new(&lights_path).exists() {
+                    scene.lights = Lights::load(&lights_path).lights;
+                }
+                return Some(scene);
+            }
             _ => {
                 log_error!("Unsupported scene format '{}' at path '{}'", format, path);
                 return None;
@@ -33,7 +50,7 @@ impl From<OBJ> for Scene {
     fn from(obj: OBJ) -> Self {
         let mut scene = Scene::default();
 
-        for obj_tri in obj.tris {
+        for obj_tri in &obj.tris {
             let mut vertices: [Vertex; 3] = [Vertex::default(); 3];
             for i in 0..3 {
                 vertices[i] = Vertex {
@@ -52,6 +69,7 @@ impl From<OBJ> for Scene {
                         .tex_coords
                         .get(obj_tri.tex_coords[i])
                         .unwrap_or(&[0.0; 2]),
+                    tangent: [0.0, 0.0, 0.0, 1.0],
                 };
             }
             scene
@@ -59,10 +77,58 @@ impl From<OBJ> for Scene {
                 .push(Triangle::new(vertices, obj_tri.material_id));
         }
 
+        // Tangents are summed per unique OBJ vertex so triangles sharing it get one smooth frame
+        let vertex_key = |obj_tri: &crate::loader::obj::Triangle, i: usize| -> [usize; 3] {
+            return [
+                obj_tri.positions[i],
+                obj_tri.tex_coords[i],
+                obj_tri.normals[i],
+            ];
+        };
+        let mut tangent_sums: HashMap<[usize; 3], (Vec3f, Vec3f)> = HashMap::new();
+        for (tri, obj_tri) in scene.tris.iter().zip(&obj.tris) {
+            let Some((tangent, bitangent)) = tri.uv_tangents() else {
+                continue;
+            };
+            for i in 0..3 {
+                let sum = tangent_sums
+                    .entry(vertex_key(obj_tri, i))
+                    .or_insert((Vec3f::from(0.0), Vec3f::from(0.0)));
+                sum.0 += tangent;
+                sum.1 += bitangent;
+            }
+        }
+        for (tri, obj_tri) in scene.tris.iter_mut().zip(&obj.tris) {
+            for i in 0..3 {
+                let Some((tangent, bitangent)) = tangent_sums.get(&vertex_key(obj_tri, i)) else {
+                    continue;
+                };
+                let normal = Vec3f::from(tri.vertices[i].normal);
+                // Gram-Schmidt against the vertex normal
+                let tangent = *tangent - normal * Vec3f::dot(normal, *tangent);
+                if tangent.length() < 1e-8 {
+                    continue;
+                }
+                let tangent = tangent.normalized();
+                let handedness = if Vec3f::dot(Vec3f::cross(normal, tangent), *bitangent) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                tri.vertices[i].tangent = [
+                    tangent.data[0],
+                    tangent.data[1],
+                    tangent.data[2],
+                    handedness,
+                ];
+            }
+        }
+
         scene.materials = obj.materials;
         scene.textures = obj.textures;
 
         BVH::build(&mut scene);
+        scene.emissive = LightTree::build(&scene);
 
         return scene;
     }
@@ -73,6 +139,8 @@ pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
     pub tex_coord: [f32; 2],
+    /// Points along increasing u, `w` is the sign of the bitangent along increasing v
+    pub tangent: [f32; 4],
 }
 
 #[derive(Clone, Copy, Default)]
@@ -89,6 +157,62 @@ impl Triangle {
         };
     }
 
+    /// Unnormalized directions of increasing u and v across the triangle,
+    /// `None` when the texture coordinates are degenerate
+    fn uv_tangents(&self) -> Option<(Vec3f, Vec3f)> {
+        let p_0 = Vec3f::from(self.vertices[0].position);
+        let edge_1 = Vec3f::from(self.vertices[1].position) - p_0;
+        let edge_2 = Vec3f::from(self.vertices[2].position) - p_0;
+
+        let [u_0, v_0] = self.vertices[0].tex_coord;
+        let (du_1, dv_1) = (
+            self.vertices[1].tex_coord[0] - u_0,
+            self.vertices[1].tex_coord[1] - v_0,
+        );
+        let (du_2, dv_2) = (
+            self.vertices[2].tex_coord[0] - u_0,
+            self.vertices[2].tex_coord[1] - v_0,
+        );
+
+        let det = du_1 * dv_2 - du_2 * dv_1;
+        if f32::abs(det) < 1e-12 {
+            return None;
+        }
+        let tangent = (edge_1 * dv_2 - edge_2 * dv_1) / det;
+        let bitangent = (edge_2 * du_1 - edge_1 * du_2) / det;
+        return Some((tangent, bitangent));
+    }
+
+    pub fn area(&self) -> f32 {
+        let p_0 = Vec3f::from(self.vertices[0].position);
+        let edge_1 = Vec3f::from(self.vertices[1].position) - p_0;
+        let edge_2 = Vec3f::from(self.vertices[2].position) - p_0;
+        return 0.5 * Vec3f::cross(edge_1, edge_2).length();
+    }
+
+    pub fn geometric_normal(&self) -> Vec3f {
+        let p_0 = Vec3f::from(self.vertices[0].position);
+        let edge_1 = Vec3f::from(self.vertices[1].position) - p_0;
+        let edge_2 = Vec3f::from(self.vertices[2].position) - p_0;
+        return Vec3f::cross(edge_1, edge_2).normalized();
+    }
+
+    /// Uniformly distributed point on the triangle from two random numbers,
+    /// returns the position and the texture coordinates there
+    pub fn sample_point(&self, u_1: f32, u_2: f32) -> (Vec3f, [f32; 2]) {
+        let r = f32::sqrt(u_1);
+        let weights = [1.0 - r, r * (1.0 - u_2), r * u_2];
+
+        let mut position = Vec3f::from(0.0);
+        let mut uv = [0.0; 2];
+        for i in 0..3 {
+            position += Vec3f::from(self.vertices[i].position) * weights[i];
+            uv[0] += self.vertices[i].tex_coord[0] * weights[i];
+            uv[1] += self.vertices[i].tex_coord[1] * weights[i];
+        }
+        return (position, uv);
+    }
+
     pub fn mid(&self) -> Vec3f {
         return Vec3f::new(
             (self.vertices[0].position[0]
@@ -114,11 +238,50 @@ pub struct Material {
     pub specular_tint: Vec3f,
     pub emission: Vec3f,
     pub transmission: f32,
+    /// Color a ray takes on after travelling `1.0 / absorption_density` units inside the material
+    pub absorption_color: Vec3f,
+    pub absorption_density: f32,
     pub ior: f32,
     pub roughness: f32,
     pub metallic: f32,
+    /// Measured optical constants for the metallic part, which is tinted by the base color without them
+    pub conductor: Option<ComplexIor>,
+    /// Stretches highlights along the tangent, 0.0 is isotropic
+    pub anisotropy: f32,
+    /// Turns the anisotropy direction around the normal, in fractions of a full turn
+    pub anisotropy_rotation: f32,
+    /// Share of the diffuse base that scatters inside the material instead of off its surface
+    pub subsurface: f32,
+    /// Overall color of light that made it back out after scattering inside
+    pub subsurface_color: Vec3f,
+    /// Average distance light travels inside before scattering, per color channel
+    pub subsurface_radius: Vec3f,
+    /// Thickness in nanometres of an interference film on the surface, 0.0 disables it
+    pub thin_film_thickness: f32,
+    pub thin_film_ior: f32,
+    /// Strength of a smooth dielectric coat over the base layer
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub clearcoat_ior: f32,
+    /// Retroreflective fabric highlight towards grazing angles
+    pub sheen: f32,
+    pub sheen_tint: Vec3f,
     pub base_color_tex_id: i32,
     pub emission_tex_id: i32,
+    /// Cutout opacity, multiplied with the opacity texture if there is one
+    pub opacity: f32,
+    pub opacity_tex_id: i32,
+    /// Tangent space normal map
+    pub normal_tex_id: i32,
+    /// Height map, brighter texels are raised
+    pub bump_tex_id: i32,
+    pub base_color_sampler: Sampler,
+    pub emission_sampler: Sampler,
+    pub opacity_sampler: Sampler,
+    pub normal_sampler: Sampler,
+    pub bump_sampler: Sampler,
+    /// Surface tilt per texel for a full black to white step in the height map
+    pub bump_strength: f32,
 }
 
 impl Default for Material {
@@ -129,11 +292,36 @@ impl Default for Material {
             specular_tint: Vec3f::new(1.0, 1.0, 1.0),
             emission: Vec3f::new(0.0, 0.0, 0.0),
             transmission: 0.0,
+            absorption_color: Vec3f::new(1.0, 1.0, 1.0),
+            absorption_density: 0.0,
             ior: 1.45,
             roughness: 1.0,
             metallic: 0.0,
+            conductor: None,
+            anisotropy: 0.0,
+            anisotropy_rotation: 0.0,
+            subsurface: 0.0,
+            subsurface_color: Vec3f::new(0.8, 0.8, 0.8),
+            subsurface_radius: Vec3f::new(1.0, 0.2, 0.1),
+            thin_film_thickness: 0.0,
+            thin_film_ior: 1.33,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.0,
+            clearcoat_ior: 1.5,
+            sheen: 0.0,
+            sheen_tint: Vec3f::new(1.0, 1.0, 1.0),
             base_color_tex_id: -1,
             emission_tex_id: -1,
+            opacity: 1.0,
+            opacity_tex_id: -1,
+            normal_tex_id: -1,
+            bump_tex_id: -1,
+            base_color_sampler: Sampler::default(),
+            emission_sampler: Sampler::default(),
+            opacity_sampler: Sampler::default(),
+            normal_sampler: Sampler::default(),
+            bump_sampler: Sampler::default(),
+            bump_strength: 1.0,
         };
     }
 }
@@ -0,0 +1,166 @@
+use crate::color;
+use crate::vector::{Vec3Swizzles, Vec3f};
+
+/// Angular radius of the sun disc as seen from the ground
+const SUN_ANGULAR_RADIUS: f32 = 0.00465;
+/// Sun luminance above the atmosphere in kcd/m²
+const SUN_LUMINANCE: f32 = 2.0e6;
+/// Converts the model's kcd/m² into renderer radiance, a clear zenith ends up around 1.0
+const LUMINANCE_SCALE: f32 = 0.1;
+/// Wavelengths in micrometers used for the red, green and blue sun transmittance
+const WAVELENGTHS: [f32; 3] = [0.680, 0.550, 0.440];
+
+/// Preetham analytic daylight model with a sun disc
+#[derive(Clone, Copy)]
+pub struct Sky {
+    pub sun_elevation: f32,
+    pub sun_azimuth: f32,
+    pub turbidity: f32,
+    sun_direction: Vec3f,
+    sun_zenith_angle: f32,
+    sun_radiance: Vec3f,
+    zenith: [f32; 3],
+    perez_luminance: [f32; 5],
+    perez_x: [f32; 5],
+    perez_y: [f32; 5],
+}
+
+impl Sky {
+    /// Elevation is measured up from the horizon, azimuth turns from -Z towards +X, both in radians
+    pub fn new(sun_elevation: f32, sun_azimuth: f32, turbidity: f32) -> Self {
+        let sun_direction = Vec3f::new(
+            f32::cos(sun_elevation) * f32::sin(sun_azimuth),
+            f32::sin(sun_elevation),
+            -f32::cos(sun_elevation) * f32::cos(sun_azimuth),
+        );
+
+        let t = turbidity;
+        let theta_s =
+            std::f32::consts::FRAC_PI_2 - sun_elevation.clamp(0.0, std::f32::consts::FRAC_PI_2);
+        let theta_s_2 = theta_s * theta_s;
+        let theta_s_3 = theta_s_2 * theta_s;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * f32::tan(chi) - 0.2155 * t + 2.4192;
+        let zenith_x = t * t * (0.00166 * theta_s_3 - 0.00375 * theta_s_2 + 0.00209 * theta_s)
+            + t * (-0.02903 * theta_s_3 + 0.06377 * theta_s_2 - 0.03202 * theta_s + 0.00394)
+            + (0.11693 * theta_s_3 - 0.21196 * theta_s_2 + 0.06052 * theta_s + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta_s_3 - 0.00610 * theta_s_2 + 0.00317 * theta_s)
+            + t * (-0.04214 * theta_s_3 + 0.08970 * theta_s_2 - 0.04153 * theta_s + 0.00516)
+            + (0.15346 * theta_s_3 - 0.26756 * theta_s_2 + 0.06670 * theta_s + 0.26688);
+
+        let perez_luminance = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let perez_x = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let perez_y = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        // Sunlight is attenuated by Rayleigh and aerosol scattering along the air mass
+        let sun_zenith_degrees = theta_s.to_degrees();
+        let air_mass =
+            1.0 / (f32::cos(theta_s) + 0.15 * f32::powf(93.885 - sun_zenith_degrees, -1.253));
+        let beta = 0.04608 * t - 0.04586;
+        let mut transmittance = Vec3f::from(0.0);
+        for i in 0..3 {
+            let rayleigh = 0.008735 * f32::powf(WAVELENGTHS[i], -4.08);
+            let aerosol = beta * f32::powf(WAVELENGTHS[i], -1.3);
+            transmittance.data[i] = f32::exp(-air_mass * (rayleigh + aerosol));
+        }
+        let sun_radiance = if sun_elevation > -SUN_ANGULAR_RADIUS {
+            transmittance * (SUN_LUMINANCE * LUMINANCE_SCALE)
+        } else {
+            Vec3f::from(0.0)
+        };
+
+        return Self {
+            sun_elevation,
+            sun_azimuth,
+            turbidity,
+            sun_direction,
+            sun_zenith_angle: theta_s,
+            sun_radiance,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            perez_luminance,
+            perez_x,
+            perez_y,
+        };
+    }
+
+    fn perez(coefficients: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = *coefficients;
+        return (1.0 + a * f32::exp(b / cos_theta))
+            * (1.0 + c * f32::exp(d * gamma) + e * f32::cos(gamma) * f32::cos(gamma));
+    }
+
+    /// Sky radiance in `direction`, including the sun disc
+    pub fn radiance(&self, direction: Vec3f) -> Vec3f {
+        // The model is undefined below the horizon, the ground sees the horizon color
+        let cos_theta = f32::max(direction.y(), 0.001);
+        let cos_gamma = Vec3f::dot(direction, self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = f32::acos(cos_gamma);
+
+        let relative = |coefficients: &[f32; 5]| -> f32 {
+            return Self::perez(coefficients, cos_theta, gamma)
+                / Self::perez(coefficients, 1.0, self.sun_zenith_angle).max(f32::EPSILON);
+        };
+
+        let luminance = f32::max(self.zenith[0] * relative(&self.perez_luminance), 0.0);
+        let x = self.zenith[1] * relative(&self.perez_x);
+        let y = self.zenith[2] * relative(&self.perez_y);
+
+        let mut color = color::xyy_to_rgb(x, y, luminance * LUMINANCE_SCALE);
+        if direction.y() < 0.0 {
+            color *= 0.3;
+        }
+        if cos_gamma >= f32::cos(SUN_ANGULAR_RADIUS) {
+            color += self.sun_radiance;
+        }
+        return color;
+    }
+
+    /// Picks a direction uniformly inside the sun disc, returning it with its solid angle density
+    pub fn sample_sun(&self, rng_state: &mut u32) -> (Vec3f, f32) {
+        let cos_max = f32::cos(SUN_ANGULAR_RADIUS);
+        let cos_theta = 1.0 - Vec3f::rand_f32(rng_state) * (1.0 - cos_max);
+        let sin_theta = f32::sqrt(f32::max(1.0 - cos_theta * cos_theta, 0.0));
+        let phi = std::f32::consts::TAU * Vec3f::rand_f32(rng_state);
+
+        let (tangent, bitangent) = Vec3f::orthonormal_basis(self.sun_direction);
+        let direction = (tangent * (sin_theta * f32::cos(phi))
+            + bitangent * (sin_theta * f32::sin(phi))
+            + self.sun_direction * cos_theta)
+            .normalized();
+        return (direction, 1.0 / (std::f32::consts::TAU * (1.0 - cos_max)));
+    }
+
+    /// Solid angle density of `sample_sun` returning `direction`
+    pub fn sun_pdf(&self, direction: Vec3f) -> f32 {
+        let cos_max = f32::cos(SUN_ANGULAR_RADIUS);
+        if Vec3f::dot(direction, self.sun_direction) < cos_max {
+            return 0.0;
+        }
+        return 1.0 / (std::f32::consts::TAU * (1.0 - cos_max));
+    }
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        return Self::new(45f32.to_radians(), 0.0, 3.0);
+    }
+}
@@ -1,49 +1,305 @@
-use crate::{loader::bmp::BMP, log_error, log_warning};
+use crate::color;
+use crate::loader::bmp::BMP;
+use crate::loader::jpeg::{self, JPEG};
+use crate::loader::png::{self, PNG};
+use crate::vector::Vec3f;
+use crate::{log_error, log_warning};
+use std::io::Read;
+use std::sync::LazyLock;
+
+static SRGB_DECODE: LazyLock<[f32; 256]> =
+    LazyLock::new(|| std::array::from_fn(|i| color::srgb_to_linear(i as f32 / 255.0)));
+static LINEAR_DECODE: LazyLock<[f32; 256]> =
+    LazyLock::new(|| std::array::from_fn(|i| i as f32 / 255.0));
+
+/// What the stored bytes of a texture represent
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum ColorSpace {
+    /// Color maps, decoded to linear values when sampled
+    #[default]
+    Srgb,
+    /// Data maps such as normals, heights and opacity, used as stored
+    Linear,
+}
+
+impl ColorSpace {
+    fn decode_table(self) -> &'static [f32; 256] {
+        match self {
+            Self::Srgb => return &SRGB_DECODE,
+            Self::Linear => return &LINEAR_DECODE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum FilterMode {
+    Nearest,
+    Bilinear,
+    /// Bilinear lookups in the two closest mip levels, blended by the footprint
+    #[default]
+    Trilinear,
+}
+
+/// How a material texture slot reads its texture
+#[derive(Clone, Copy, Default)]
+pub struct Sampler {
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+    pub filter: FilterMode,
+}
 
 #[derive(Clone, Default)]
-pub struct Texture {
+pub struct MipLevel {
     pub width: usize,
     pub height: usize,
-    pub pixel_data: Vec<[u8; 3]>,
+    /// Rows from bottom to top like BMP stores them, loaders of other formats flip theirs to match
+    pub pixel_data: Vec<[u8; 4]>,
+}
+
+#[derive(Clone, Default)]
+pub struct Texture {
+    /// Full resolution image followed by successively halved copies down to 1x1
+    pub mip_levels: Vec<MipLevel>,
+    /// Whether any texel is less than fully opaque
+    pub has_alpha: bool,
+    /// Color space of the RGB channels, alpha is always linear
+    pub color_space: ColorSpace,
 }
 
 impl Texture {
-    pub fn load(path: &str) -> Option<Self> {
+    pub fn load(path: &str, color_space: ColorSpace) -> Option<Self> {
         if !std::fs::exists(path).unwrap() {
             log_error!("Could not find texture at path: '{}'", path);
             return None;
         }
 
+        let mut signature: Vec<u8> = Vec::new();
+        std::fs::File::open(path)
+            .unwrap()
+            .take(png::SIGNATURE.len() as u64)
+            .read_to_end(&mut signature)
+            .unwrap();
+
+        if signature.starts_with(&png::SIGNATURE) {
+            return Some(Self::from_png(PNG::load(path), color_space));
+        } else if signature.starts_with(&jpeg::SIGNATURE) {
+            return Some(Self::from_jpeg(JPEG::load(path), color_space));
+        } else if signature.starts_with(b"BM") {
+            return Some(Self::from_bmp(BMP::load(path), color_space));
+        }
         let format = path.split(".").last().unwrap();
-        match format {
-            "bmp" => Some(BMP::load(path).into()),
-            _ => {
-                log_warning!("Unsupported texture format '{}' at path '{}'", format, path);
-                return None;
+        log_warning!("Unsupported texture format '{}' at path '{}'", format, path);
+        return None;
+    }
+
+    /// `footprint` is the width of the ray cone at the hit point in texture coordinate units
+    pub fn color_at(&self, uv: [f32; 2], sampler: &Sampler, footprint: f32) -> Vec3f {
+        let rgba = self.sample(uv, sampler, footprint);
+        return Vec3f::new(rgba[0], rgba[1], rgba[2]);
+    }
+
+    /// Filtered linear RGBA lookup with all channels in the range 0.0 - 1.0
+    pub fn sample(&self, uv: [f32; 2], sampler: &Sampler, footprint: f32) -> [f32; 4] {
+        let decode = self.color_space.decode_table();
+        let base = &self.mip_levels[0];
+        let texels = footprint * f32::sqrt((base.width * base.height) as f32);
+        let max_level = (self.mip_levels.len() - 1) as f32;
+        // A zero footprint gives -inf, which clamps to the full resolution level
+        let lod = f32::log2(texels).clamp(0.0, max_level);
+
+        match sampler.filter {
+            FilterMode::Nearest => {
+                let level = &self.mip_levels[f32::round(lod) as usize];
+                let x = uv[0] * level.width as f32;
+                let y = uv[1] * level.height as f32;
+                return level.texel(f32::floor(x) as i32, f32::floor(y) as i32, sampler, decode);
+            }
+            FilterMode::Bilinear => {
+                return self.mip_levels[f32::round(lod) as usize].bilinear(uv, sampler, decode);
+            }
+            FilterMode::Trilinear => {
+                let level = f32::floor(lod);
+                let t = lod - level;
+                let fine = self.mip_levels[level as usize].bilinear(uv, sampler, decode);
+                if t <= 0.0 {
+                    return fine;
+                }
+                let coarse = self.mip_levels[level as usize + 1].bilinear(uv, sampler, decode);
+
+                let mut rgba = [0.0; 4];
+                for i in 0..4 {
+                    rgba[i] = fine[i] * (1.0 - t) + coarse[i] * t;
+                }
+                return rgba;
             }
         }
     }
 
-    pub fn color_at(&self, uv: [f32; 2]) -> [u8; 3] {
-        let i: i32 = (uv[0] * self.width as f32) as i32;
-        let j: i32 = (uv[1] * self.height as f32) as i32;
-        let mut index: i32 = i + (j * self.width as i32);
-        while index > self.pixel_data.len() as i32 - 1 {
-            index -= self.pixel_data.len() as i32 - 1;
-        }
-        while index < 0 {
-            index += self.pixel_data.len() as i32 - 1;
+    /// Tangent space normal maps average to a light blue, close to (0.5, 0.5, 1.0)
+    pub fn is_normal_map(&self) -> bool {
+        let average = self.mip_levels.last().unwrap().pixel_data[0];
+        return (96..=160).contains(&average[0])
+            && (96..=160).contains(&average[1])
+            && average[2] >= 192;
+    }
+
+    fn from_bmp(bmp: BMP, color_space: ColorSpace) -> Self {
+        return Self::from_pixels(
+            bmp.width as usize,
+            bmp.height as usize,
+            bmp.pixel_data
+                .iter()
+                .map(|color| [color[0], color[1], color[2], 255])
+                .collect(),
+            color_space,
+        );
+    }
+
+    fn from_png(png: PNG, color_space: ColorSpace) -> Self {
+        return Self::from_pixels(
+            png.width as usize,
+            png.height as usize,
+            png.pixel_data,
+            color_space,
+        );
+    }
+
+    fn from_jpeg(jpeg: JPEG, color_space: ColorSpace) -> Self {
+        return Self::from_pixels(
+            jpeg.width as usize,
+            jpeg.height as usize,
+            jpeg.pixel_data
+                .iter()
+                .map(|color| [color[0], color[1], color[2], 255])
+                .collect(),
+            color_space,
+        );
+    }
+
+    fn from_pixels(
+        width: usize,
+        height: usize,
+        pixel_data: Vec<[u8; 4]>,
+        color_space: ColorSpace,
+    ) -> Self {
+        let has_alpha = pixel_data.iter().any(|texel| texel[3] < 255);
+        let mut mip_levels = vec![MipLevel {
+            width,
+            height,
+            pixel_data,
+        }];
+        loop {
+            let last = mip_levels.last().unwrap();
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = last.downsampled(color_space);
+            mip_levels.push(next);
         }
-        return self.pixel_data[index as usize];
+        return Self {
+            mip_levels,
+            has_alpha,
+            color_space,
+        };
     }
 }
 
-impl From<BMP> for Texture {
-    fn from(bmp: BMP) -> Self {
+impl MipLevel {
+    /// Halves the level with a box filter, odd edges reuse their last row or column
+    fn downsampled(&self, color_space: ColorSpace) -> Self {
+        let decode = color_space.decode_table();
+        let width = usize::max(self.width / 2, 1);
+        let height = usize::max(self.height / 2, 1);
+        let mut pixel_data: Vec<[u8; 4]> = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let y_0 = usize::min(2 * y, self.height - 1);
+            let y_1 = usize::min(2 * y + 1, self.height - 1);
+            for x in 0..width {
+                let x_0 = usize::min(2 * x, self.width - 1);
+                let x_1 = usize::min(2 * x + 1, self.width - 1);
+
+                let mut sum = [0.0; 4];
+                for (sx, sy) in [(x_0, y_0), (x_1, y_0), (x_0, y_1), (x_1, y_1)] {
+                    let texel = self.pixel_data[sx + sy * self.width];
+                    for i in 0..3 {
+                        sum[i] += decode[texel[i] as usize];
+                    }
+                    sum[3] += texel[3] as f32 / 255.0;
+                }
+
+                let mut texel = [0u8; 4];
+                for i in 0..4 {
+                    let mut average = sum[i] / 4.0;
+                    if i < 3 && color_space == ColorSpace::Srgb {
+                        average = color::linear_to_srgb(average);
+                    }
+                    texel[i] = f32::round(average * 255.0).clamp(0.0, 255.0) as u8;
+                }
+                pixel_data.push(texel);
+            }
+        }
         return Self {
-            width: bmp.width as usize,
-            height: bmp.height as usize,
-            pixel_data: bmp.pixel_data,
+            width,
+            height,
+            pixel_data,
         };
     }
+
+    fn bilinear(&self, uv: [f32; 2], sampler: &Sampler, decode: &[f32; 256]) -> [f32; 4] {
+        // Texel centers sit at half integer coordinates
+        let x = uv[0] * self.width as f32 - 0.5;
+        let y = uv[1] * self.height as f32 - 0.5;
+        let x_0 = f32::floor(x);
+        let y_0 = f32::floor(y);
+        let tx = x - x_0;
+        let ty = y - y_0;
+
+        let c_00 = self.texel(x_0 as i32, y_0 as i32, sampler, decode);
+        let c_10 = self.texel(x_0 as i32 + 1, y_0 as i32, sampler, decode);
+        let c_01 = self.texel(x_0 as i32, y_0 as i32 + 1, sampler, decode);
+        let c_11 = self.texel(x_0 as i32 + 1, y_0 as i32 + 1, sampler, decode);
+
+        let mut rgba = [0.0; 4];
+        for i in 0..4 {
+            let bottom = c_00[i] * (1.0 - tx) + c_10[i] * tx;
+            let top = c_01[i] * (1.0 - tx) + c_11[i] * tx;
+            rgba[i] = bottom * (1.0 - ty) + top * ty;
+        }
+        return rgba;
+    }
+
+    fn texel(&self, x: i32, y: i32, sampler: &Sampler, decode: &[f32; 256]) -> [f32; 4] {
+        let x = Self::wrap(x, self.width as i32, sampler.wrap_u);
+        let y = Self::wrap(y, self.height as i32, sampler.wrap_v);
+        let texel = self.pixel_data[x + y * self.width];
+        return [
+            decode[texel[0] as usize],
+            decode[texel[1] as usize],
+            decode[texel[2] as usize],
+            texel[3] as f32 / 255.0,
+        ];
+    }
+
+    fn wrap(coord: i32, size: i32, mode: WrapMode) -> usize {
+        match mode {
+            WrapMode::Repeat => return coord.rem_euclid(size) as usize,
+            WrapMode::Clamp => return coord.clamp(0, size - 1) as usize,
+            WrapMode::Mirror => {
+                let period = coord.rem_euclid(2 * size);
+                if period < size {
+                    return period as usize;
+                } else {
+                    return (2 * size - 1 - period) as usize;
+                }
+            }
+        }
+    }
 }
@@ -35,14 +35,16 @@ impl Vec3f {
         return incident - (normal * 2.0 * Self::dot(incident, normal));
     }
 
-    pub fn refract(incident: Self, normal: Self, eta: f32) -> Self {
+    /// Bends `incident` through a surface with the ratio `eta` of the incident and transmitted
+    /// indices of refraction, `None` when it is totally internally reflected instead
+    pub fn refract(incident: Self, normal: Self, eta: f32) -> Option<Self> {
         let k =
             1.0 - (eta * eta) * (1.0 - (Self::dot(normal, incident) * Self::dot(normal, incident)));
         if k < 0.0 {
-            return Self::new(0.0, 0.0, 0.0);
+            return None;
         } else {
             let eta_dot_n_i = eta * Self::dot(normal, incident);
-            return (incident * eta) - (Self::from(eta_dot_n_i + f32::sqrt(k)) * normal);
+            return Some((incident * eta) - (Self::from(eta_dot_n_i + f32::sqrt(k)) * normal));
         }
     }
 
@@ -136,14 +138,31 @@ impl Vec3f {
         }
     }
 
-    pub fn linear_to_gamma(linear: Self) -> Self {
-        let mut gamma = Self::new(0.0, 0.0, 0.0);
-        for i in 0..3 {
-            if linear.data[i] > 0.0 {
-                gamma.data[i] = f32::sqrt(linear.data[i]);
-            }
-        }
-        return gamma;
+    /// Returns a direction around `normal` with a density of cos(theta) / pi
+    pub fn rand_cosine_hemisphere(input: &mut u32, normal: Self) -> Self {
+        let r = f32::sqrt(Self::rand_f32(input));
+        let phi = std::f32::consts::TAU * Self::rand_f32(input);
+        let z = f32::sqrt(f32::max(1.0 - r * r, 0.0));
+        let (tangent, bitangent) = Self::orthonormal_basis(normal);
+        return tangent * (r * f32::cos(phi)) + bitangent * (r * f32::sin(phi)) + normal * z;
+    }
+
+    /// Returns two unit vectors that form a right handed orthonormal basis with `normal`
+    pub fn orthonormal_basis(normal: Self) -> (Self, Self) {
+        let sign = f32::copysign(1.0, normal.z());
+        let a = -1.0 / (sign + normal.z());
+        let b = normal.x() * normal.y() * a;
+        let tangent = Self::new(
+            1.0 + sign * normal.x() * normal.x() * a,
+            sign * b,
+            -sign * normal.x(),
+        );
+        let bitangent = Self::new(b, sign + normal.y() * normal.y() * a, -normal.y());
+        return (tangent, bitangent);
+    }
+
+    pub fn luminance(self) -> f32 {
+        return (0.2126 * self.x()) + (0.7152 * self.y()) + (0.0722 * self.z());
     }
 }
 